@@ -0,0 +1,141 @@
+//! Compares tracing camera rays through `BVHNode`, `FlatBVH` and
+//! `TriangleMesh`, run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ray_tracer::bvh::{BVHNode, BVHSplit};
+use ray_tracer::camera::Camera;
+use ray_tracer::flat_bvh::FlatBVH;
+use ray_tracer::hittable::{HitRecord, Hittable};
+use ray_tracer::hittable_list::HittableList;
+use ray_tracer::material::{Lambertian, Material};
+use ray_tracer::ray::Ray;
+use ray_tracer::triangle::Triangle;
+use ray_tracer::triangle_mesh::{TriangleMesh, TriangleMeshData};
+use ray_tracer::scenes::build_scene;
+use ray_tracer::texture::SolidTexture;
+use ray_tracer::util;
+use ray_tracer::vec3::Vec3;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+const SIZE: usize = 64;
+/// Rings and segments of the bumpy sphere, 2 * 64 * 64 = 8192 triangles
+const SEGMENTS: usize = 64;
+
+fn camera_rays(camera: &Camera) -> Vec<Ray> {
+    util::seed_random(0);
+    (0..SIZE * SIZE)
+        .map(|i| {
+            let u = ((i % SIZE) as f64 + util::random_double()) / SIZE as f64;
+            let v = ((i / SIZE) as f64 + util::random_double()) / SIZE as f64;
+            camera.get_ray(u, v)
+        })
+        .collect()
+}
+
+fn trace<T: Hittable>(world: &T, rays: &[Ray]) -> usize {
+    let mut rec = HitRecord::new();
+    rays.iter().filter(|r| world.hit(r, 0.001, f64::INFINITY, &mut rec)).count()
+}
+
+/// Labels a split with the algorithm each layout builds it with, `BVHNode`
+/// sorts along a random axis for its median split while the flat layouts
+/// partition along the longest axis of the centroids
+fn split_name(split: BVHSplit, flat: bool) -> &'static str {
+    match (split, flat) {
+        (BVHSplit::Median, false) => "random-axis median",
+        (BVHSplit::Median, true) => "longest-axis median",
+        (BVHSplit::Sah, _) => "sah",
+    }
+}
+
+fn compare(c: &mut Criterion, name: &str, mut objects: HittableList, rays: &[Ray]) {
+    let mut group = c.benchmark_group(name);
+
+    for split in [BVHSplit::Median, BVHSplit::Sah] {
+        let flat = FlatBVH::from_list(&objects, 0.0, 1.0, split);
+        let tree = BVHNode::from_list_with_split(&mut objects, 0.0, 1.0, split);
+        assert_eq!(trace(&tree, rays), trace(&flat, rays));
+
+        group.bench_with_input(BenchmarkId::new("BVHNode", split_name(split, false)), rays, |b, rays| b.iter(|| trace(&tree, rays)));
+        group.bench_with_input(BenchmarkId::new("FlatBVH", split_name(split, true)), rays, |b, rays| b.iter(|| trace(&flat, rays)));
+    }
+
+    group.finish();
+}
+
+fn cornell_scenes(c: &mut Criterion) {
+    for name in &["Cornell Box", "Cornell Box with Cubes", "Cornell Box with Glass Sphere (SLOW)"] {
+        util::seed_random(0);
+        let scene = build_scene(name, SIZE, SIZE).unwrap();
+        let rays = camera_rays(&scene.camera);
+        compare(c, name, scene.objects, &rays);
+    }
+}
+
+/// A `Triangle` for each face of `data`, all using its first material
+fn triangles(data: &TriangleMeshData) -> HittableList {
+    let mut list = HittableList::new_with_capacity(data.triangles.len());
+    for &[a, b, c] in &data.triangles {
+        let [a, b, c] = [a, b, c].map(|i| i as usize);
+        let (p1, p2, p3) = (data.positions[a], data.positions[b], data.positions[c]);
+        let material = data.materials[0].clone();
+        let triangle = if data.normals.is_empty() {
+            Triangle::new_flat(p1, p2, p3, material)
+        } else {
+            Triangle::new_smooth(p1, p2, p3, data.normals[a], data.normals[b], data.normals[c], material)
+        };
+        list.add(Arc::new(triangle));
+    }
+    list
+}
+
+/// A unit sphere with its radius raised and lowered in a grid of bumps, flat
+/// shaded so every triangle has its own face normal
+fn bumpy_sphere() -> TriangleMeshData {
+    let mut positions = Vec::with_capacity((SEGMENTS + 1) * SEGMENTS);
+    for ring in 0..=SEGMENTS {
+        let theta = PI * ring as f64 / SEGMENTS as f64;
+        for segment in 0..SEGMENTS {
+            let phi = 2.0 * PI * segment as f64 / SEGMENTS as f64;
+            let radius = 1.0 + 0.05 * (8.0 * theta).sin() * (8.0 * phi).sin();
+            positions.push(Vec3::new(phi.cos() * theta.sin(), theta.cos(), phi.sin() * theta.sin()).scale(radius));
+        }
+    }
+
+    let index = |ring: usize, segment: usize| (ring * SEGMENTS + segment % SEGMENTS) as u32;
+    let mut triangles = Vec::with_capacity(2 * SEGMENTS * SEGMENTS);
+    for ring in 0..SEGMENTS {
+        for segment in 0..SEGMENTS {
+            let (a, b) = (index(ring, segment), index(ring, segment + 1));
+            let (c, d) = (index(ring + 1, segment), index(ring + 1, segment + 1));
+            triangles.push([a, c, b]);
+            triangles.push([b, c, d]);
+        }
+    }
+
+    let material: Arc<dyn Material> = Arc::new(Lambertian::new(SolidTexture::new(0.5, 0.5, 0.5)));
+    TriangleMeshData { positions, triangles, materials: vec![material], ..TriangleMeshData::default() }
+}
+
+fn bumpy_sphere_mesh(c: &mut Criterion) {
+    let data = bumpy_sphere();
+    let triangles = triangles(&data);
+    let camera = Camera::new(Vec3::new(0.0, 1.0, -4.0), Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 40.0, 1.0, 0.0, 4.0);
+    let rays = camera_rays(&camera);
+    let hits = trace(&triangles, &rays);
+    compare(c, "Bumpy sphere", triangles, &rays);
+
+    let mut group = c.benchmark_group("Bumpy sphere");
+    for split in [BVHSplit::Median, BVHSplit::Sah] {
+        let mesh = TriangleMesh::new(data.clone(), split);
+        assert_eq!(trace(&mesh, &rays), hits);
+
+        group.bench_with_input(BenchmarkId::new("TriangleMesh", split_name(split, true)), &rays[..], |b, rays| b.iter(|| trace(&mesh, rays)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, cornell_scenes, bumpy_sphere_mesh);
+criterion_main!(benches);
@@ -55,6 +55,15 @@ impl AABB {
         self.max
     }
 
+    /// The total area of the box's six faces, zero for empty boxes
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        if d.x() < 0.0 || d.y() < 0.0 || d.z() < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
     pub fn hit(&self, r: &Ray, tmin: &mut f64, tmax: &mut f64) -> bool {
         for a in 0..3 {
             let inv_d = 1.0 / r.direction()[a];
@@ -3,10 +3,12 @@ use crate::hittable::{Hittable, HitRecord};
 use crate::ray::Ray;
 use crate::aabb::AABB;
 use crate::vec3::Vec3;
+use crate::lights::Emitter;
 
 use std::sync::Arc;
 use crate::util;
 
+#[derive(Clone)]
 pub struct XYRect {
     material: Arc<dyn Material>,
     x0: f64,
@@ -49,8 +51,37 @@ impl Hittable for XYRect {
             &Vec3::new(self.x1, self.y1, self.k + 0.0001));
         true
     }
+
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(*o, *v, 0.0), 0.001, f64::INFINITY, &mut rec) {
+            return 0.0;
+        }
+
+        let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+        let distance_squared = rec.t * rec.t * v.length_squared();
+        let cosine = (v.dot(&rec.normal) / v.length()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        let random_point = Vec3::new(util::random_double_range(self.x0, self.x1), util::random_double_range(self.y0, self.y1), self.k);
+        random_point - *o
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        if self.material.is_emissive() {
+            emitters.push(Emitter {
+                object: Arc::new(self.clone()),
+                area: (self.x1 - self.x0) * (self.y1 - self.y0),
+                emission: self.material.average_emission(),
+            });
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct XZRect {
     material: Arc<dyn Material>,
     x0: f64,
@@ -96,7 +127,7 @@ impl Hittable for XZRect {
 
     fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
         let mut rec = HitRecord::new();
-        if !self.hit(&Ray::new(*o, *v, 0.0), 0.001, std::f64::INFINITY, &mut rec) {
+        if !self.hit(&Ray::new(*o, *v, 0.0), 0.001, f64::INFINITY, &mut rec) {
             return 0.0;
         }
 
@@ -111,8 +142,19 @@ impl Hittable for XZRect {
         let random_point = Vec3::new(util::random_double_range(self.x0, self.x1), self.k, util::random_double_range(self.z0, self.z1));
         random_point - *o
     }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        if self.material.is_emissive() {
+            emitters.push(Emitter {
+                object: Arc::new(self.clone()),
+                area: (self.x1 - self.x0) * (self.z1 - self.z0),
+                emission: self.material.average_emission(),
+            });
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct YZRect {
     material: Arc<dyn Material>,
     y0: f64,
@@ -155,4 +197,32 @@ impl Hittable for YZRect {
             &Vec3::new(self.k + 0.0001, self.y1, self.z1));
         true
     }
+
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(*o, *v, 0.0), 0.001, f64::INFINITY, &mut rec) {
+            return 0.0;
+        }
+
+        let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+        let distance_squared = rec.t * rec.t * v.length_squared();
+        let cosine = (v.dot(&rec.normal) / v.length()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        let random_point = Vec3::new(self.k, util::random_double_range(self.y0, self.y1), util::random_double_range(self.z0, self.z1));
+        random_point - *o
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        if self.material.is_emissive() {
+            emitters.push(Emitter {
+                object: Arc::new(self.clone()),
+                area: (self.y1 - self.y0) * (self.z1 - self.z0),
+                emission: self.material.average_emission(),
+            });
+        }
+    }
 }
@@ -0,0 +1,151 @@
+//! What rays that leave the scene without hitting anything see.
+//!
+//! Environment maps are equirectangular images, their width covers every
+//! direction around the vertical axis and their height goes from straight up
+//! at the top row to straight down at the bottom row.
+
+use crate::pdf::{EnvironmentPdf, Pdf};
+use crate::tonemap::luminance;
+use crate::vec3::Vec3;
+
+use image::Rgb32FImage;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+pub trait Background: Sync + Send {
+    /// The light arriving from `direction`
+    fn value(&self, direction: &Vec3) -> Vec3;
+
+    /// A distribution of directions toward the bright parts of the
+    /// background, for backgrounds worth sampling directly
+    fn pdf(&self) -> Option<Arc<dyn Pdf>> {
+        None
+    }
+}
+
+/// The same color in every direction
+#[derive(Clone)]
+pub struct SolidBackground {
+    color: Vec3,
+}
+
+impl SolidBackground {
+    pub fn new(r: f64, g: f64, b: f64) -> Self {
+        SolidBackground { color: Vec3::new(r, g, b) }
+    }
+}
+
+impl From<Vec3> for SolidBackground {
+    fn from(v: Vec3) -> Self {
+        SolidBackground::new(v.x(), v.y(), v.z())
+    }
+}
+
+impl Background for SolidBackground {
+    fn value(&self, _direction: &Vec3) -> Vec3 {
+        self.color
+    }
+}
+
+/// A blend from `bottom` looking straight down to `top` looking straight up
+#[derive(Clone)]
+pub struct GradientBackground {
+    bottom: Vec3,
+    top: Vec3,
+}
+
+impl GradientBackground {
+    pub fn new(bottom: Vec3, top: Vec3) -> Self {
+        GradientBackground { bottom, top }
+    }
+
+    /// The white to light blue sky from the first book
+    pub fn sky() -> Self {
+        GradientBackground::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Background for GradientBackground {
+    fn value(&self, direction: &Vec3) -> Vec3 {
+        let t = 0.5 * (direction.unit_vector().y() + 1.0);
+        self.bottom.scale(1.0 - t) + self.top.scale(t)
+    }
+}
+
+/// An equirectangular image of the light arriving from every direction,
+/// sampled in proportion to its brightness
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    /// Radiance of each pixel, row by row from the top, already scaled by the intensity
+    pixels: Vec<Vec3>,
+    /// Radians the map is turned counterclockwise about +y
+    rotation: f64,
+    pdf: Arc<EnvironmentPdf>,
+}
+
+impl EnvironmentMap {
+    /// Loads an HDR or EXR image, turned `rotation` degrees about +y with its
+    /// radiance multiplied by `intensity`
+    pub fn open(path: &str, rotation: f64, intensity: f64) -> image::ImageResult<Self> {
+        Ok(EnvironmentMap::from_image(&image::open(path)?.into_rgb32f(), rotation, intensity))
+    }
+
+    pub fn from_image(image: &Rgb32FImage, rotation: f64, intensity: f64) -> Self {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let pixels: Vec<Vec3> = image.pixels()
+            .map(|p| Vec3::new(p.0[0] as f64, p.0[1] as f64, p.0[2] as f64).scale(intensity).max(0.0))
+            .collect();
+        let rotation = rotation.to_radians();
+
+        // Rows near the poles cover less solid angle, so their pixels send less light
+        let weights: Vec<f64> = pixels.iter()
+            .enumerate()
+            .map(|(i, p)| luminance(p).max(0.0) * latitude_cos((i / width) as f64 + 0.5, height))
+            .collect();
+        let pdf = Arc::new(EnvironmentPdf::new(width, height, &weights, rotation));
+
+        EnvironmentMap { width, height, pixels, rotation, pdf }
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn value(&self, direction: &Vec3) -> Vec3 {
+        let (u, v) = direction_to_uv(direction, self.rotation);
+        let (i, j) = uv_to_pixel(u, v, self.width, self.height);
+        self.pixels[j * self.width + i]
+    }
+
+    fn pdf(&self) -> Option<Arc<dyn Pdf>> {
+        Some(self.pdf.clone())
+    }
+}
+
+/// The cosine of the latitude at `row` rows down from the top of a map `height` rows high
+fn latitude_cos(row: f64, height: usize) -> f64 {
+    (PI * (0.5 - row / height as f64)).cos()
+}
+
+/// The texture coordinates a direction maps to on a map turned `rotation`
+/// radians about +y, using the same layout as `Sphere`'s texture coordinates
+pub(crate) fn direction_to_uv(direction: &Vec3, rotation: f64) -> (f64, f64) {
+    let d = direction.unit_vector();
+    let phi = d.z().atan2(d.x()) + rotation;
+    let theta = d.y().clamp(-1.0, 1.0).asin();
+    let u = 1.0 - (phi + PI) / (2.0 * PI);
+    (u - u.floor(), (theta + PI / 2.0) / PI)
+}
+
+/// The unit direction texture coordinates map to, the inverse of `direction_to_uv`
+pub(crate) fn uv_to_direction(u: f64, v: f64, rotation: f64) -> Vec3 {
+    let phi = (1.0 - u) * 2.0 * PI - PI - rotation;
+    let theta = v * PI - PI / 2.0;
+    Vec3::new(theta.cos() * phi.cos(), theta.sin(), theta.cos() * phi.sin())
+}
+
+/// The column and row of the pixel holding texture coordinates `u` and `v`
+pub(crate) fn uv_to_pixel(u: f64, v: f64, width: usize, height: usize) -> (usize, usize) {
+    let i = ((u * width as f64) as usize).min(width - 1);
+    let j = (((1.0 - v) * height as f64) as usize).min(height - 1);
+    (i, j)
+}
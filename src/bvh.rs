@@ -1,17 +1,137 @@
 use crate::hittable::{Hittable, HitRecord};
 use crate::aabb::AABB;
 use crate::ray::Ray;
-use crate::util::random_int_range;
+use crate::util::{self, random_int_range};
 use crate::hittable_list::HittableList;
+use crate::vec3::Vec3;
+use crate::lights::Emitter;
 
 use rayon::slice::ParallelSliceMut;
+use serde::Deserialize;
 use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
 
+/// Number of centroid bins tried along each axis by the SAH builders
+pub(crate) const SAH_BINS: usize = 16;
+
+/// How a BVH chooses where to split its objects
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BVHSplit {
+    /// Sorts along a random axis and splits at the median, quick to build
+    #[default]
+    Median,
+    /// Bins object centroids and splits where the surface area heuristic is lowest
+    /// https://www.pbr-book.org/3ed-2018/Primitives_and_Intersection_Acceleration/Bounding_Volume_Hierarchies
+    Sah,
+}
+
+impl BVHSplit {
+    pub const NAMES: [&'static str; 2] = ["median", "sah"];
+}
+
+impl FromStr for BVHSplit {
+    type Err = UnknownBVHSplitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "median" => Ok(BVHSplit::Median),
+            "sah" => Ok(BVHSplit::Sah),
+            _ => Err(UnknownBVHSplitError { name: s.to_string() }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownBVHSplitError {
+    name: String,
+}
+
+impl fmt::Display for UnknownBVHSplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown BVH split '{}', valid splits are: {}", self.name, BVHSplit::NAMES.join(", "))
+    }
+}
+
+impl std::error::Error for UnknownBVHSplitError {}
+
+/// Size and quality of a BVH, used to compare builders
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BVHStats {
+    /// Number of nodes, not counting the objects themselves
+    pub nodes: usize,
+    /// Number of nodes on the longest path from the root to an object
+    pub depth: usize,
+    /// Expected cost of tracing a ray that hits the root's box through the
+    /// tree, where testing a box and intersecting an object both cost 1
+    pub sah_cost: f64,
+}
+
+impl BVHStats {
+    /// Stats of a node holding `objects` objects directly
+    pub(crate) fn leaf(objects: usize) -> Self {
+        BVHStats { nodes: 1, depth: 1, sah_cost: 1.0 + objects as f64 }
+    }
+
+    /// Stats of a node with a box of `area` and child nodes given by their
+    /// box area and stats, every child's box is tested when the node is hit
+    /// and its contents only when the ray hits the child's box
+    pub(crate) fn interior(area: f64, children: &[(f64, BVHStats)], objects: usize) -> Self {
+        let mut stats = BVHStats { nodes: 1, depth: 1, sah_cost: 1.0 + objects as f64 };
+
+        for (child_area, child) in children {
+            let probability = if area > 0.0 && area.is_finite() { child_area / area } else { 1.0 };
+
+            stats.nodes += child.nodes;
+            stats.depth = stats.depth.max(1 + child.depth);
+            stats.sah_cost += 1.0 + probability * (child.sah_cost - 1.0);
+        }
+
+        stats
+    }
+}
+
+impl fmt::Display for BVHStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} nodes, depth {}, SAH cost {:.2}", self.nodes, self.depth, self.sah_cost)
+    }
+}
+
 pub struct BVHNode {
     left: Arc<dyn Hittable>,
     right: Arc<dyn Hittable>,
     aabb_box: AABB,
+    stats: BVHStats,
+}
+
+/// The bounding box and centroid of the object at `index`, cached while building
+pub(crate) struct Primitive {
+    pub index: usize,
+    pub aabb_box: AABB,
+    pub centroid: Vec3,
+}
+
+impl Primitive {
+    pub fn new(index: usize, aabb_box: AABB) -> Self {
+        let centroid = (aabb_box.min() + aabb_box.max()).scale(0.5);
+        Primitive { index, aabb_box, centroid }
+    }
+
+    /// Caches the bounding boxes of `objects`
+    pub fn from_objects(objects: &[Arc<dyn Hittable>], time0: f64, time1: f64) -> Vec<Self> {
+        objects.iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let mut aabb_box = AABB::new_max();
+                if !object.bounding_box(time0, time1, &mut aabb_box) {
+                    eprintln!("No bounding box in BVH constructor.")
+                }
+                Primitive::new(index, aabb_box)
+            })
+            .collect()
+    }
 }
 
 impl BVHNode {
@@ -21,14 +141,17 @@ impl BVHNode {
 
         let object_span = end - start;
 
-        let (left, right) = if object_span == 1 {
-            (objects[start].clone(), objects[start].clone())
+        if object_span == 0 {
+            BVHNode::empty()
+        } else if object_span == 1 {
+            BVHNode::join((objects[start].clone(), None), (objects[start].clone(), None), time0, time1)
         } else if object_span == 2 {
-            if box_compare(objects[start].clone(), objects[start + 1].clone(), axis) {
+            let (left, right) = if box_compare(objects[start].clone(), objects[start + 1].clone(), axis) {
                 (objects[start].clone(), objects[start + 1].clone())
             } else {
                 (objects[start + 1].clone(), objects[start].clone())
-            }
+            };
+            BVHNode::join((left, None), (right, None), time0, time1)
         } else {
             objects[start..end].par_sort_by(|a, b| {
                 if box_compare(a.clone(), b.clone(), axis) {
@@ -40,12 +163,87 @@ impl BVHNode {
 
             let mid = start + object_span / 2;
 
-            let left = Arc::new(BVHNode::new(objects, start, mid, time0, time1));
-            let right = Arc::new(BVHNode::new(objects, mid, end, time0, time1));
+            let left = BVHNode::new(objects, start, mid, time0, time1);
+            let right = BVHNode::new(objects, mid, end, time0, time1);
+
+            BVHNode::join(left.into_child(), right.into_child(), time0, time1)
+        }
+    }
+
+    /// Builds a BVH with the binned surface area heuristic, which takes longer
+    /// than `new` but gives trees that are much faster to trace for meshes
+    pub fn new_sah(objects: &[Arc<dyn Hittable>], time0: f64, time1: f64) -> Self {
+        let mut primitives = Primitive::from_objects(objects, time0, time1);
+        BVHNode::build_sah(objects, &mut primitives, time0, time1)
+    }
+
+    pub fn from_list(objects: &mut HittableList, time0: f64, time1: f64) -> Self {
+        BVHNode::from_list_with_split(objects, time0, time1, BVHSplit::Median)
+    }
 
-            (left as Arc<dyn Hittable>, right as Arc<dyn Hittable>)
+    pub fn from_list_with_split(objects: &mut HittableList, time0: f64, time1: f64, split: BVHSplit) -> Self {
+        match split {
+            BVHSplit::Median => {
+                let len = objects.objects.len();
+                BVHNode::new(&mut objects.objects, 0, len, time0, time1)
+            }
+            BVHSplit::Sah => BVHNode::new_sah(&objects.objects, time0, time1),
+        }
+    }
+
+    pub fn stats(&self) -> BVHStats {
+        self.stats
+    }
+
+    fn build_sah(objects: &[Arc<dyn Hittable>], primitives: &mut [Primitive], time0: f64, time1: f64) -> Self {
+        let object = |p: &Primitive| (objects[p.index].clone(), None);
+        match primitives.len() {
+            0 => return BVHNode::empty(),
+            1 => return BVHNode::join(object(&primitives[0]), object(&primitives[0]), time0, time1),
+            2 => return BVHNode::join(object(&primitives[0]), object(&primitives[1]), time0, time1),
+            _ => {}
+        }
+
+        let mut centroid_box = AABB::new_max();
+        for p in primitives.iter() {
+            centroid_box.add_point(&p.centroid);
+        }
+
+        let mid = match sah_split(primitives, &centroid_box) {
+            Some((axis, bin, _)) => {
+                let bin_of = |p: &Primitive| centroid_bin(&p.centroid, &centroid_box, axis);
+                partition(primitives, |p| bin_of(p) <= bin)
+            }
+            None => primitives.len() / 2,
         };
 
+        // Every centroid fell on the same side, fall back to an even split
+        let mid = if mid == 0 || mid == primitives.len() { primitives.len() / 2 } else { mid };
+
+        let (left, right) = primitives.split_at_mut(mid);
+        let left = BVHNode::build_sah(objects, left, time0, time1);
+        let right = BVHNode::build_sah(objects, right, time0, time1);
+
+        BVHNode::join(left.into_child(), right.into_child(), time0, time1)
+    }
+
+    /// A node without objects, which nothing hits and which has no bounding box
+    fn empty() -> Self {
+        let nothing: Arc<dyn Hittable> = Arc::new(HittableList::new());
+        BVHNode { left: nothing.clone(), right: nothing, aabb_box: AABB::new_max(), stats: BVHStats::leaf(0) }
+    }
+
+    fn into_child(self) -> (Arc<dyn Hittable>, Option<BVHStats>) {
+        let stats = self.stats;
+        (Arc::new(self), Some(stats))
+    }
+
+    /// Makes a node from two children, children that are BVH nodes pass their stats along
+    fn join(left: (Arc<dyn Hittable>, Option<BVHStats>), right: (Arc<dyn Hittable>, Option<BVHStats>),
+            time0: f64, time1: f64) -> Self {
+        let (left, left_stats) = left;
+        let (right, right_stats) = right;
+
         let mut box_left = AABB::new_max();
         let mut box_right = AABB::new_max();
 
@@ -56,12 +254,14 @@ impl BVHNode {
 
         let aabb_box = box_left.surrounding_box(&box_right);
 
-        BVHNode { left, right, aabb_box }
-    }
+        // Children that are objects rather than nodes are intersected without a box test
+        let mut children = Vec::with_capacity(2);
+        children.extend(left_stats.map(|stats| (box_left.surface_area(), stats)));
+        children.extend(right_stats.map(|stats| (box_right.surface_area(), stats)));
+        let objects = 2 - children.len();
+        let stats = BVHStats::interior(aabb_box.surface_area(), &children, objects);
 
-    pub fn from_list(objects: &mut HittableList, time0: f64, time1: f64) -> Self {
-        let len = objects.objects.len();
-        BVHNode::new(&mut objects.objects, 0, len, time0, time1)
+        BVHNode { left, right, aabb_box, stats }
     }
 }
 
@@ -82,7 +282,29 @@ impl Hittable for BVHNode {
 
     fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
         *output_box = self.aabb_box;
-        true
+        // Only an empty node's box has its corners the wrong way around
+        self.aabb_box.min().x() <= self.aabb_box.max().x()
+    }
+
+    /// Picks either child with equal chance, so objects in shallow leaves are sampled more often
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        0.5 * (self.left.pdf_value(o, v) + self.right.pdf_value(o, v))
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        if util::random_double() < 0.5 {
+            self.left.random(o)
+        } else {
+            self.right.random(o)
+        }
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        self.left.emitters(emitters);
+        // A leaf with one object holds it on both sides
+        if !Arc::ptr_eq(&self.left, &self.right) {
+            self.right.emitters(emitters);
+        }
     }
 }
 
@@ -97,3 +319,69 @@ fn box_compare(a: Arc<dyn Hittable>, b: Arc<dyn Hittable>, axis: usize) -> bool
 
     box_a.min()[axis] < box_b.min()[axis]
 }
+
+pub(crate) fn centroid_bin(centroid: &Vec3, centroid_box: &AABB, axis: usize) -> usize {
+    let extent = centroid_box.max()[axis] - centroid_box.min()[axis];
+    let bin = (SAH_BINS as f64 * (centroid[axis] - centroid_box.min()[axis]) / extent) as usize;
+    bin.min(SAH_BINS - 1)
+}
+
+/// Finds the axis and last bin of the left side of the cheapest split along
+/// with its cost, the sum of each side's object count times its box's area.
+/// Returns `None` when all the centroids are in the same place.
+pub(crate) fn sah_split(primitives: &[Primitive], centroid_box: &AABB) -> Option<(usize, usize, f64)> {
+    let mut best: Option<(usize, usize, f64)> = None;
+
+    for axis in 0..3 {
+        if centroid_box.max()[axis] - centroid_box.min()[axis] <= 0.0 {
+            continue;
+        }
+
+        let mut counts = [0usize; SAH_BINS];
+        let mut boxes = [AABB::new_max(); SAH_BINS];
+        for p in primitives {
+            let bin = centroid_bin(&p.centroid, centroid_box, axis);
+            counts[bin] += 1;
+            boxes[bin] = boxes[bin].surrounding_box(&p.aabb_box);
+        }
+
+        // Sweep from the right so each split's right side cost is known
+        let mut right_costs = [0.0; SAH_BINS];
+        let mut right_box = AABB::new_max();
+        let mut right_count = 0;
+        for bin in (1..SAH_BINS).rev() {
+            right_box = right_box.surrounding_box(&boxes[bin]);
+            right_count += counts[bin];
+            right_costs[bin - 1] = right_count as f64 * right_box.surface_area();
+        }
+
+        let mut left_box = AABB::new_max();
+        let mut left_count = 0;
+        for bin in 0..SAH_BINS - 1 {
+            left_box = left_box.surrounding_box(&boxes[bin]);
+            left_count += counts[bin];
+            if left_count == 0 || left_count == primitives.len() {
+                continue;
+            }
+
+            let cost = left_count as f64 * left_box.surface_area() + right_costs[bin];
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, bin, cost));
+            }
+        }
+    }
+
+    best
+}
+
+/// Moves the primitives matching `pred` to the front and returns how many there are
+pub(crate) fn partition<F: Fn(&Primitive) -> bool>(primitives: &mut [Primitive], pred: F) -> usize {
+    let mut mid = 0;
+    for i in 0..primitives.len() {
+        if pred(&primitives[i]) {
+            primitives.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
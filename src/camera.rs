@@ -10,7 +10,6 @@ pub struct Camera {
     vertical: Vec3,
     u: Vec3,
     v: Vec3,
-    w: Vec3,
     lens_radius: f64,
     t0: f64,
     t1: f64,
@@ -23,6 +22,7 @@ impl Camera {
         Camera::new_timed(lookfrom, lookat, vup, vfov, aspect, aperture, focus_dist, 0.0, 0.0)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new_timed(lookfrom: Vec3, lookat: Vec3, vup: Vec3,
                      vfov: f64,
                      aspect: f64, aperture: f64, focus_dist: f64,
@@ -52,7 +52,6 @@ impl Camera {
             origin,
             u,
             v,
-            w,
             lens_radius,
             t0,
             t1,
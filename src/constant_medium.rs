@@ -1,5 +1,5 @@
 use crate::hittable::{Hittable, HitRecord};
-use crate::material::Isotropic;
+use crate::material::{Isotropic, HenyeyGreenstein, Material};
 use crate::ray::Ray;
 use crate::aabb::AABB;
 
@@ -9,25 +9,32 @@ use crate::util::random_double;
 use crate::vec3::Vec3;
 use crate::texture::Texture;
 
-pub struct ConstantMedium<T>
-    where T: Texture {
+pub struct ConstantMedium {
     boundary: Arc<dyn Hittable>,
-    phase_function: Isotropic<T>,
+    phase_function: Arc<dyn Material>,
     neg_inv_density: f64,
 }
 
-impl<T> ConstantMedium<T>
-    where T: Texture {
-    pub fn new(boundrary: Arc<dyn Hittable>, albedo: T, density: f64) -> Self
-        where T: Texture {
+impl ConstantMedium {
+    pub fn new<T>(boundrary: Arc<dyn Hittable>, albedo: T, density: f64) -> Self
+        where T: Texture + 'static {
+        ConstantMedium::with_phase_function(boundrary, Arc::new(Isotropic::new(albedo)), density)
+    }
+
+    /// A medium that scatters following the Henyey-Greenstein phase function,
+    /// fog and clouds typically have a `g` between 0.7 and 0.9
+    pub fn new_anisotropic<T>(boundrary: Arc<dyn Hittable>, albedo: T, density: f64, g: f64) -> Self
+        where T: Texture + 'static {
+        ConstantMedium::with_phase_function(boundrary, Arc::new(HenyeyGreenstein::new(albedo, g)), density)
+    }
+
+    pub fn with_phase_function(boundrary: Arc<dyn Hittable>, phase_function: Arc<dyn Material>, density: f64) -> Self {
         let neg_inv_density = -1.0 / density;
-        let phase_function = Isotropic::new(albedo);
         ConstantMedium { boundary: boundrary, phase_function, neg_inv_density }
     }
 }
 
-impl<T: 'static> Hittable for ConstantMedium<T>
-    where T: Texture {
+impl Hittable for ConstantMedium {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
         let mut rec1 = HitRecord::new();
         let mut rec2 = HitRecord::new();
@@ -64,7 +71,7 @@ impl<T: 'static> Hittable for ConstantMedium<T>
 
         rec.normal = Vec3::new(1.0, 0.0, 0.0);
         rec.front_face = true;
-        rec.mat = Arc::new(self.phase_function.clone());
+        rec.mat = self.phase_function.clone();
 
         true
     }
@@ -7,6 +7,7 @@ use crate::aarect::{XYRect, XZRect, YZRect};
 use crate::hittable::{Hittable, HitRecord, FlipFace};
 use crate::ray::Ray;
 use crate::aabb::AABB;
+use crate::lights::Emitter;
 
 pub struct Cube {
     cube_min: Vec3,
@@ -46,4 +47,18 @@ impl Hittable for Cube {
         *output_box = AABB::new(&self.cube_min, &self.cube_max);
         true
     }
+
+    /// Samples one of the six sides at random, so half the samples land on
+    /// sides facing away from `o`
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        self.sides.pdf_value(o, v)
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        self.sides.random(o)
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        self.sides.emitters(emitters)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,275 @@
+use crate::hittable::{Hittable, HitRecord};
+use crate::aabb::AABB;
+use crate::ray::Ray;
+use crate::hittable_list::HittableList;
+use crate::bvh::{self, BVHSplit, BVHStats, Primitive};
+use crate::vec3::Vec3;
+use crate::util;
+use crate::lights::Emitter;
+
+use rand::prelude::SliceRandom;
+
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+/// Leaves are only made for at most this many objects
+const MAX_LEAF_OBJECTS: usize = 4;
+
+/// Past this depth nodes are split evenly, which bounds the traversal stack
+const MAX_SAH_DEPTH: usize = 32;
+
+/// Size of the traversal stack, enough for `MAX_SAH_DEPTH` levels plus even
+/// splits of any number of objects that fits in memory
+const STACK_SIZE: usize = 64;
+
+/// A BVH stored as a flat array of nodes in depth first order.
+///
+/// Unlike `BVHNode` each step of a traversal is an index into the array
+/// rather than a dynamic call, leaves hold up to `MAX_LEAF_OBJECTS` objects,
+/// and the child nearer to the ray's origin along the split axis is visited
+/// first so closer hits can cull the farther child.
+pub struct FlatBVH {
+    nodes: FlatNodes,
+    objects: Vec<Arc<dyn Hittable>>,
+}
+
+/// The nodes of a flat BVH over primitives stored elsewhere, the leaves refer
+/// to ranges of the primitives in the order returned by `FlatNodes::build`
+pub(crate) struct FlatNodes {
+    nodes: Vec<FlatNode>,
+    stats: BVHStats,
+}
+
+#[derive(Clone, Copy)]
+struct FlatNode {
+    aabb_box: AABB,
+    /// Index of the first primitive for leaves, and of the second child for
+    /// interior nodes as the first child always follows its parent
+    offset: usize,
+    /// Number of primitives in a leaf, zero for interior nodes
+    count: usize,
+    /// Axis the children of an interior node were split along
+    axis: usize,
+}
+
+impl FlatBVH {
+    pub fn new(objects: &[Arc<dyn Hittable>], time0: f64, time1: f64, split: BVHSplit) -> Self {
+        let mut primitives = Primitive::from_objects(objects, time0, time1);
+        let nodes = FlatNodes::build(&mut primitives, split);
+
+        FlatBVH {
+            nodes,
+            objects: primitives.iter().map(|p| objects[p.index].clone()).collect(),
+        }
+    }
+
+    pub fn from_list(objects: &HittableList, time0: f64, time1: f64, split: BVHSplit) -> Self {
+        FlatBVH::new(&objects.objects, time0, time1, split)
+    }
+
+    pub fn stats(&self) -> BVHStats {
+        self.nodes.stats()
+    }
+}
+
+impl Hittable for FlatBVH {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.nodes.hit(r, t_min, t_max, |i, closest| {
+            if self.objects[i].hit(r, t_min, closest, rec) {
+                Some(rec.t)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        match self.nodes.bounding_box() {
+            Some(aabb_box) => {
+                *output_box = aabb_box;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Picks one of the objects with equal chance
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f64 = self.objects.iter().map(|object| object.pdf_value(o, v)).sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        match util::with_rng(|rng| self.objects.choose(rng)) {
+            Some(object) => object.random(o),
+            None => Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        for object in &self.objects {
+            object.emitters(emitters);
+        }
+    }
+}
+
+impl FlatNodes {
+    /// Builds the nodes over `primitives`, which are reordered so each leaf's
+    /// primitives are next to each other
+    pub fn build(primitives: &mut [Primitive], split: BVHSplit) -> Self {
+        let mut nodes = FlatNodes {
+            nodes: Vec::with_capacity(2 * primitives.len()),
+            stats: BVHStats { nodes: 0, depth: 0, sah_cost: 0.0 },
+        };
+
+        if !primitives.is_empty() {
+            nodes.stats = nodes.build_node(primitives, 0, split, 0);
+        }
+
+        nodes
+    }
+
+    pub fn stats(&self) -> BVHStats {
+        self.stats
+    }
+
+    /// The memory used by the nodes in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<FlatNode>()
+    }
+
+    pub fn bounding_box(&self) -> Option<AABB> {
+        self.nodes.first().map(|root| root.aabb_box)
+    }
+
+    /// Appends the subtree for `primitives`, which start at `first` in the
+    /// final order, and returns its stats
+    fn build_node(&mut self, primitives: &mut [Primitive], first: usize, split: BVHSplit, depth: usize) -> BVHStats {
+        let mut aabb_box = AABB::new_max();
+        let mut centroid_box = AABB::new_max();
+        for p in primitives.iter() {
+            aabb_box = aabb_box.surrounding_box(&p.aabb_box);
+            centroid_box.add_point(&p.centroid);
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(FlatNode { aabb_box, offset: first, count: primitives.len(), axis: 0 });
+
+        let n = primitives.len();
+        // The axis and number of primitives in the first child, or `None` for a leaf
+        let split_at = match split {
+            BVHSplit::Sah if depth < MAX_SAH_DEPTH => match bvh::sah_split(primitives, &centroid_box) {
+                Some((axis, bin, cost)) => {
+                    // Both costs are relative to intersecting one object, a split also pays for testing the child boxes
+                    let split_cost = 2.0 + cost / aabb_box.surface_area();
+                    if n <= MAX_LEAF_OBJECTS && (n as f64) <= split_cost {
+                        None
+                    } else {
+                        Some((axis, bvh::partition(primitives, |p| bvh::centroid_bin(&p.centroid, &centroid_box, axis) <= bin)))
+                    }
+                }
+                None if n <= MAX_LEAF_OBJECTS => None,
+                None => Some((0, n / 2)),
+            },
+            _ if n <= 2 => None,
+            _ => {
+                let axis = longest_axis(&centroid_box);
+                primitives.select_nth_unstable_by(n / 2, |a, b| {
+                    a.centroid[axis].partial_cmp(&b.centroid[axis]).unwrap_or(Ordering::Equal)
+                });
+                Some((axis, n / 2))
+            }
+        };
+
+        let (axis, mid) = match split_at {
+            Some((axis, mid)) if mid == 0 || mid == n => (axis, n / 2),
+            Some(split_at) => split_at,
+            None => return BVHStats::leaf(n),
+        };
+
+        let (left, right) = primitives.split_at_mut(mid);
+        let left_area = subtree_area(left);
+        let right_area = subtree_area(right);
+
+        let left_stats = self.build_node(left, first, split, depth + 1);
+        let second = self.nodes.len();
+        let right_stats = self.build_node(right, first + mid, split, depth + 1);
+
+        self.nodes[index] = FlatNode { aabb_box, offset: second, count: 0, axis };
+
+        BVHStats::interior(aabb_box.surface_area(), &[(left_area, left_stats), (right_area, right_stats)], 0)
+    }
+
+    /// Visits the leaves whose boxes `r` hits from nearest to farthest,
+    /// calling `hit_primitive` with the index of each of their primitives and
+    /// the closest hit so far. It returns the distance of a closer hit or `None`.
+    pub fn hit<F>(&self, r: &Ray, t_min: f64, t_max: f64, mut hit_primitive: F) -> bool
+        where F: FnMut(usize, f64) -> Option<f64> {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let mut closest = t_max;
+        let mut hit_anything = false;
+
+        let mut stack = [0; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut index = 0;
+
+        loop {
+            let node = &self.nodes[index];
+            let mut tmin = t_min;
+            let mut tmax = closest;
+
+            if node.aabb_box.hit(r, &mut tmin, &mut tmax) {
+                if node.count > 0 {
+                    for i in node.offset..node.offset + node.count {
+                        if let Some(t) = hit_primitive(i, closest) {
+                            hit_anything = true;
+                            closest = t;
+                        }
+                    }
+                } else {
+                    let (near, far) = if r.direction()[node.axis] < 0.0 {
+                        (node.offset, index + 1)
+                    } else {
+                        (index + 1, node.offset)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    index = near;
+                    continue;
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            index = stack[stack_len];
+        }
+
+        hit_anything
+    }
+}
+
+fn subtree_area(primitives: &[Primitive]) -> f64 {
+    primitives.iter()
+        .fold(AABB::new_max(), |aabb_box, p| aabb_box.surrounding_box(&p.aabb_box))
+        .surface_area()
+}
+
+fn longest_axis(aabb_box: &AABB) -> usize {
+    let extent = aabb_box.max() - aabb_box.min();
+    if extent.x() >= extent.y() && extent.x() >= extent.z() {
+        0
+    } else if extent.y() >= extent.z() {
+        1
+    } else {
+        2
+    }
+}
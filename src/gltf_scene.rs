@@ -0,0 +1,347 @@
+//! Loader for glTF 2.0 scenes, both `.gltf` and `.glb`.
+//!
+//! The default scene's node hierarchy is flattened with every mesh's
+//! triangles moved into world space and gathered into one `TriangleMesh`.
+//! Metallic-roughness materials become the closest of the crate's materials,
+//! emissive materials become lights, and the first camera in the hierarchy
+//! becomes the scene's camera.
+//! https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html
+
+use crate::background::SolidBackground;
+use crate::bvh::BVHSplit;
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::lights::{Lights, LightWeighting};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::scenes::Scene;
+use crate::texture::{ImageTexture, SolidTexture, TintedTexture};
+use crate::triangle_mesh::{TriangleMesh, TriangleMeshData};
+use crate::vec3::Vec3;
+
+use nalgebra::{Matrix3, Matrix4, Point3, Vector3};
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum GltfError {
+    Import(gltf::Error),
+    /// The file has no triangles to render
+    Empty,
+    /// A primitive whose data does not fit together
+    Invalid(String),
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Import(e) => write!(f, "could not import glTF: {}", e),
+            GltfError::Empty => write!(f, "the glTF scene has no triangles"),
+            GltfError::Invalid(message) => write!(f, "invalid glTF: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+impl From<gltf::Error> for GltfError {
+    fn from(e: gltf::Error) -> Self {
+        GltfError::Import(e)
+    }
+}
+
+/// The vertical field of view used when the file has no camera
+const DEFAULT_VFOV: f64 = 40.0;
+
+/// Loads a glTF file as a scene rendered at `width` by `height` pixels.
+pub fn load_gltf(path: &str, width: usize, height: usize) -> Result<Scene, GltfError> {
+    let (document, buffers, images) = gltf::import(path)?;
+    let aspect = width as f64 / height as f64;
+
+    let mut loader = Loader {
+        buffers: &buffers,
+        geometry: TriangleMeshData::default(),
+        camera: None,
+        aspect,
+    };
+
+    // The default material is always the last one
+    let default_material_id = document.materials().len() as u32;
+    loader.geometry.materials = document.materials().map(|m| gltf_material(&images, &m)).collect();
+    loader.geometry.materials.push(Arc::new(Lambertian::new(SolidTexture::new(0.8, 0.8, 0.8))));
+
+    let scene = document.default_scene().or_else(|| document.scenes().next());
+    for node in scene.iter().flat_map(|scene| scene.nodes()) {
+        loader.visit(&node, Matrix4::identity(), default_material_id)?;
+    }
+
+    if loader.geometry.triangles.is_empty() {
+        return Err(GltfError::Empty);
+    }
+
+    let geometry = TriangleMesh::new(loader.geometry, BVHSplit::Sah);
+    eprintln!("glTF '{}' imported with {} triangles, using {:.1} MB.", path, geometry.triangle_count(),
+              geometry.memory_usage() as f64 / 1_000_000.0);
+
+    let camera = match loader.camera {
+        Some(camera) => camera,
+        None => framing_camera(&geometry, aspect),
+    };
+
+    let mut objects = HittableList::new();
+    objects.add(Arc::new(geometry));
+
+    let lights = Lights::from_objects(&objects, LightWeighting::Power);
+    let background = if lights.is_empty() {
+        // Without emissive materials the scene is lit by the background
+        Vec3::new(0.70, 0.80, 1.00)
+    } else {
+        Vec3::zero()
+    };
+
+    Ok(Scene {
+        objects,
+        camera,
+        background: Arc::new(SolidBackground::from(background)),
+        lights: lights.into_shared(),
+        missing_images: Vec::new(),
+    })
+}
+
+struct Loader<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    geometry: TriangleMeshData,
+    camera: Option<Camera>,
+    aspect: f64,
+}
+
+impl<'a> Loader<'a> {
+    fn visit(&mut self, node: &gltf::Node, parent: Matrix4<f64>, default_material_id: u32) -> Result<(), GltfError> {
+        let local = node.transform().matrix();
+        let transform = parent * Matrix4::from_fn(|row, column| local[column][row] as f64);
+
+        if let (Some(camera), None) = (node.camera(), &self.camera) {
+            self.camera = Some(self.convert_camera(&camera, &transform));
+        }
+
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                self.add_primitive(&primitive, &transform, default_material_id)?;
+            }
+        }
+
+        for child in node.children() {
+            self.visit(&child, transform, default_material_id)?;
+        }
+        Ok(())
+    }
+
+    fn convert_camera(&self, camera: &gltf::Camera, transform: &Matrix4<f64>) -> Camera {
+        let vfov = match camera.projection() {
+            gltf::camera::Projection::Perspective(perspective) => (perspective.yfov() as f64).to_degrees(),
+            gltf::camera::Projection::Orthographic(_) => {
+                eprintln!("Orthographic glTF cameras are not supported, using a perspective camera.");
+                DEFAULT_VFOV
+            }
+        };
+
+        // glTF cameras look down their local -z axis with +y up
+        let lookfrom = to_vec3(&transform.transform_point(&Point3::origin()).coords);
+        let forward = to_vec3(&transform.transform_vector(&-Vector3::z()));
+        let up = to_vec3(&transform.transform_vector(&Vector3::y()));
+
+        Camera::new(lookfrom, lookfrom + forward, up, vfov, self.aspect, 0.0, 1.0)
+    }
+
+    fn add_primitive(&mut self, primitive: &gltf::Primitive, transform: &Matrix4<f64>,
+                     default_material_id: u32) -> Result<(), GltfError> {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            eprintln!("Skipping glTF primitive with unsupported mode {:?}.", primitive.mode());
+            return Ok(());
+        }
+
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+        let positions: Vec<Vec3> = match reader.read_positions() {
+            Some(positions) => positions
+                .map(|p| to_vec3(&transform.transform_point(&Point3::new(p[0] as f64, p[1] as f64, p[2] as f64)).coords))
+                .collect(),
+            None => return Ok(()),
+        };
+
+        // Normals are transformed by the inverse transpose so they stay perpendicular under non uniform scaling
+        let normal_transform = transform.try_inverse().unwrap_or_else(Matrix4::identity).transpose();
+        let normals: Vec<Vec3> = reader.read_normals()
+            .map(|normals| normals
+                .map(|n| to_vec3(&normal_transform.transform_vector(&Vector3::new(n[0] as f64, n[1] as f64, n[2] as f64))).unit_vector())
+                .collect())
+            .unwrap_or_default();
+        // glTF puts the origin of texture coordinates at the top left while `ImageTexture` puts it at the bottom left
+        let uvs: Vec<(f64, f64)> = reader.read_tex_coords(0)
+            .map(|uvs| uvs.into_f32().map(|uv| (uv[0] as f64, 1.0 - uv[1] as f64)).collect())
+            .unwrap_or_default();
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+
+        if (!normals.is_empty() && normals.len() != positions.len()) || (!uvs.is_empty() && uvs.len() != positions.len()) {
+            return Err(GltfError::Invalid("primitive attributes have different vertex counts".to_string()));
+        }
+        if let Some(index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+            return Err(GltfError::Invalid(format!("index {} of a primitive with {} vertices", index, positions.len())));
+        }
+
+        // A mirrored transform flips the winding order, which decides which side is the front
+        let mirrored = Matrix3::from_fn(|row, column| transform[(row, column)]).determinant() < 0.0;
+        let triangles: Vec<[u32; 3]> = indices.chunks_exact(3)
+            .map(|t| if mirrored { [t[0], t[2], t[1]] } else { [t[0], t[1], t[2]] })
+            .collect();
+
+        let material_id = primitive.material().index().map_or(default_material_id, |i| i as u32);
+        append(&mut self.geometry, &positions, &normals, &uvs, &triangles, material_id);
+        Ok(())
+    }
+}
+
+/// Adds a primitive's vertices and triangles to `data`, filling in missing
+/// normals and texture coordinates when other primitives have them
+fn append(data: &mut TriangleMeshData, positions: &[Vec3], normals: &[Vec3], uvs: &[(f64, f64)],
+          triangles: &[[u32; 3]], material_id: u32) {
+    // Every vertex needs a normal once any primitive has them, so flat
+    // primitives get their own vertices carrying their face normals
+    if normals.is_empty() && !data.normals.is_empty() {
+        let flat = FlatVertices::new(positions, uvs, triangles);
+        return append(data, &flat.positions, &flat.normals, &flat.uvs, &flat.triangles, material_id);
+    }
+    if !normals.is_empty() && data.normals.is_empty() && !data.positions.is_empty() {
+        // The primitives so far were all flat
+        let flat = FlatVertices::new(&data.positions, &data.uvs, &data.triangles);
+        data.positions = flat.positions;
+        data.normals = flat.normals;
+        data.uvs = flat.uvs;
+        data.triangles = flat.triangles;
+    }
+    data.normals.extend_from_slice(normals);
+
+    let offset = data.positions.len() as u32;
+    if !uvs.is_empty() || !data.uvs.is_empty() {
+        if data.uvs.is_empty() {
+            data.uvs.resize(data.positions.len(), (0.0, 0.0));
+        }
+        if uvs.is_empty() {
+            data.uvs.extend(std::iter::repeat_n((0.0, 0.0), positions.len()));
+        } else {
+            data.uvs.extend_from_slice(uvs);
+        }
+    }
+
+    data.positions.extend_from_slice(positions);
+    data.triangles.extend(triangles.iter().map(|t| [t[0] + offset, t[1] + offset, t[2] + offset]));
+    data.material_ids.extend(std::iter::repeat_n(material_id, triangles.len()));
+}
+
+/// Triangles with three vertices of their own carrying their face normal,
+/// so smooth shading leaves them flat
+struct FlatVertices {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    /// Empty when the triangles had no texture coordinates
+    uvs: Vec<(f64, f64)>,
+    triangles: Vec<[u32; 3]>,
+}
+
+impl FlatVertices {
+    fn new(positions: &[Vec3], uvs: &[(f64, f64)], triangles: &[[u32; 3]]) -> Self {
+        let mut flat = FlatVertices {
+            positions: Vec::with_capacity(3 * triangles.len()),
+            normals: Vec::with_capacity(3 * triangles.len()),
+            uvs: Vec::with_capacity(if uvs.is_empty() { 0 } else { 3 * triangles.len() }),
+            triangles: (0..triangles.len() as u32).map(|t| [3 * t, 3 * t + 1, 3 * t + 2]).collect(),
+        };
+
+        for &[a, b, c] in triangles {
+            let (a, b, c) = (a as usize, b as usize, c as usize);
+            let normal = (positions[b] - positions[a]).cross(&(positions[c] - positions[a])).unit_vector();
+            for &i in &[a, b, c] {
+                flat.positions.push(positions[i]);
+                flat.normals.push(normal);
+                if !uvs.is_empty() {
+                    flat.uvs.push(uvs[i]);
+                }
+            }
+        }
+
+        flat
+    }
+}
+
+/// Picks the closest of the crate's materials to a glTF material.
+///
+/// Emissive materials become lights, transmissive ones glass with the
+/// material's index of refraction, mostly metallic ones metal with the
+/// roughness as fuzz, and the rest Lambertian with the base color, times
+/// the base color texture when there is one.
+fn gltf_material(images: &[gltf::image::Data], material: &gltf::Material) -> Arc<dyn Material> {
+    let pbr = material.pbr_metallic_roughness();
+    let base = pbr.base_color_factor();
+    let base_color = Vec3::new(base[0] as f64, base[1] as f64, base[2] as f64);
+
+    let strength = material.emissive_strength().unwrap_or(1.0) as f64;
+    let e = material.emissive_factor();
+    let emission = Vec3::new(e[0] as f64, e[1] as f64, e[2] as f64).scale(strength);
+
+    if emission.x().max(emission.y()).max(emission.z()) > 0.0 {
+        return Arc::new(DiffuseLight::new(SolidTexture::from(emission)));
+    }
+
+    if material.transmission().is_some_and(|t| t.transmission_factor() > 0.5) {
+        return Arc::new(Dielectric::new(material.ior().unwrap_or(1.5) as f64));
+    }
+
+    if pbr.metallic_factor() >= 0.5 {
+        return Arc::new(Metal::new(&base_color, pbr.roughness_factor() as f64));
+    }
+
+    let texture = pbr.base_color_texture()
+        .and_then(|info| images.get(info.texture().source().index()))
+        .and_then(to_image);
+    match texture {
+        Some(image) => Arc::new(Lambertian::new(TintedTexture::new(ImageTexture::from_image(image), base_color))),
+        None => Arc::new(Lambertian::new(SolidTexture::from(base_color))),
+    }
+}
+
+fn to_image(data: &gltf::image::Data) -> Option<image::DynamicImage> {
+    use gltf::image::Format;
+    use image::{DynamicImage, ImageBuffer};
+
+    let (width, height) = (data.width, data.height);
+    let pixels = data.pixels.clone();
+    let image = match data.format {
+        Format::R8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, pixels)?),
+        Format::R8G8 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, pixels)?),
+        Format::R8G8B8 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, pixels)?),
+        Format::R8G8B8A8 => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, pixels)?),
+        format => {
+            eprintln!("Unsupported glTF texture format {:?}, using the base color instead.", format);
+            return None;
+        }
+    };
+    Some(image)
+}
+
+fn to_vec3(v: &Vector3<f64>) -> Vec3 {
+    Vec3::new(v.x, v.y, v.z)
+}
+
+/// A camera looking at the whole scene along -z, for files without a camera
+fn framing_camera(geometry: &TriangleMesh, aspect: f64) -> Camera {
+    let mut aabb_box = crate::aabb::AABB::new_max();
+    geometry.bounding_box(0.0, 0.0, &mut aabb_box);
+
+    let center = (aabb_box.min() + aabb_box.max()).scale(0.5);
+    let radius = (aabb_box.max() - aabb_box.min()).length() / 2.0;
+    let distance = radius / (DEFAULT_VFOV.to_radians() / 2.0).sin();
+
+    Camera::new(center + Vec3::new(0.0, 0.0, distance), center, Vec3::new(0.0, 1.0, 0.0), DEFAULT_VFOV, aspect, 0.0, 1.0)
+}
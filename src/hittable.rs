@@ -3,6 +3,7 @@ use crate::ray::Ray;
 use crate::material::{Material, Lambertian};
 use crate::aabb::AABB;
 use crate::texture::SolidTexture;
+use crate::lights::Emitter;
 
 use std::sync::Arc;
 use std::f64;
@@ -18,6 +19,12 @@ pub struct HitRecord {
     pub front_face: bool,
 }
 
+impl Default for HitRecord {
+    fn default() -> Self {
+        HitRecord::new()
+    }
+}
+
 impl HitRecord {
     pub fn new() -> Self {
         HitRecord {
@@ -41,12 +48,38 @@ impl HitRecord {
 pub trait Hittable: Sync + Send {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
     fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut AABB) -> bool;
-    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+    fn pdf_value(&self, _o: &Vec3, _v: &Vec3) -> f64 {
         0.0
     }
-    fn random(&self, o: &Vec3) -> Vec3 {
+    fn random(&self, _o: &Vec3) -> Vec3 {
         Vec3::new(1.0, 0.0, 0.0)
     }
+    /// Adds the parts of this object with emissive materials to `emitters`,
+    /// each able to be sampled as a light on its own
+    fn emitters(&self, _emitters: &mut Vec<Emitter>) {}
+}
+
+impl<T> Hittable for Arc<T>
+    where T: Hittable + ?Sized {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        (**self).hit(r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut AABB) -> bool {
+        (**self).bounding_box(t0, t1, output_box)
+    }
+
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        (**self).pdf_value(o, v)
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        (**self).random(o)
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        (**self).emitters(emitters)
+    }
 }
 
 pub struct Translate<T>
@@ -71,9 +104,8 @@ impl<T> Hittable for Translate<T>
             return false;
         }
 
+        // Moving the ray does not change which face it hits, so the normal and face are kept
         rec.p = rec.p + self.offset;
-        let norm = rec.normal;
-        rec.set_face_normal(&moved_r, &norm);
 
         true
     }
@@ -87,11 +119,28 @@ impl<T> Hittable for Translate<T>
 
         true
     }
+
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        self.object.pdf_value(&(*o - self.offset), v)
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        self.object.random(&(*o - self.offset))
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        let first = emitters.len();
+        self.object.emitters(emitters);
+        for emitter in &mut emitters[first..] {
+            emitter.object = Arc::new(Translate::new(emitter.object.clone(), self.offset));
+        }
+    }
 }
 
 pub struct RotateY<T>
     where T: Hittable {
     object: T,
+    angle: f64,
     sin_theta: f64,
     cos_theta: f64,
     has_box: bool,
@@ -107,66 +156,49 @@ impl<T> RotateY<T>
         let mut bbox = AABB::new_max();
         let has_box = object.bounding_box(0.0, 1.0, &mut bbox);
 
-        let mut min = [f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY];
-        let mut max = [f64::INFINITY, f64::INFINITY, f64::INFINITY];
-
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
-                    let x = i as f64 * bbox.min().x() + (1.0 - i as f64) * bbox.min.x();
-                    let y = j as f64 * bbox.min().y() + (1.0 - j as f64) * bbox.min.y();
-                    let z = k as f64 * bbox.min().z() + (1.0 - k as f64) * bbox.min.z();
+        let mut rotated = RotateY { object, angle, sin_theta, cos_theta, has_box, bbox };
 
-                    let newx = cos_theta * x + sin_theta * z;
-                    let newz = -sin_theta * cos_theta * z;
-
-                    let tester = [newx, y, newz];
-
-                    for c in 0..3 {
-                        min[c] = min[c].min(tester[c]);
-                        max[c] = max[c].max(tester[c]);
-                    }
+        // The rotated box is the box around the eight rotated corners
+        let mut rotated_box = AABB::new_max();
+        for &x in &[bbox.min().x(), bbox.max().x()] {
+            for &y in &[bbox.min().y(), bbox.max().y()] {
+                for &z in &[bbox.min().z(), bbox.max().z()] {
+                    rotated_box.add_point(&rotated.to_world(&Vec3::new(x, y, z)));
                 }
             }
         }
+        rotated.bbox = rotated_box;
+
+        rotated
+    }
 
-        bbox = AABB::new(
-            &Vec3::new(min[0], min[1], min[2]),
-            &Vec3::new(max[0], max[1], max[2]));
+    /// Rotates a point or direction from world space into the object's space
+    fn to_object(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(self.cos_theta * v.x() - self.sin_theta * v.z(),
+                  v.y(),
+                  self.sin_theta * v.x() + self.cos_theta * v.z())
+    }
 
-        RotateY { object, sin_theta, cos_theta, has_box, bbox }
+    /// Rotates a point or direction from the object's space into world space
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(self.cos_theta * v.x() + self.sin_theta * v.z(),
+                  v.y(),
+                  -self.sin_theta * v.x() + self.cos_theta * v.z())
     }
 }
 
 impl<T> Hittable for RotateY<T>
     where T: Hittable {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let mut origin = r.origin();
-        let mut direction = r.direction();
-
-        origin[0] = self.cos_theta * r.origin()[0] - self.sin_theta * r.origin()[2];
-        origin[2] = self.sin_theta * r.origin()[0] + self.cos_theta * r.origin()[2];
-
-        direction[0] = self.cos_theta * r.direction()[0] - self.sin_theta * r.direction()[2];
-        direction[2] = self.sin_theta * r.direction()[0] + self.cos_theta * r.direction()[2];
-
-        let rotated_r = Ray::new(origin, direction, r.time());
+        let rotated_r = Ray::new(self.to_object(&r.origin()), self.to_object(&r.direction()), r.time());
 
         if !self.object.hit(&rotated_r, t_min, t_max, rec) {
             return false;
         }
 
-        let mut p = rec.p;
-        let mut normal = rec.normal;
-
-        p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
-        p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
-
-        normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-        normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
-
-        rec.p = p;
-        rec.set_face_normal(&rotated_r, &normal);
+        // The object's normal already faces the ray, and rotating both keeps which face was hit
+        rec.p = self.to_world(&rec.p);
+        rec.normal = self.to_world(&rec.normal);
 
         true
     }
@@ -175,6 +207,23 @@ impl<T> Hittable for RotateY<T>
         *output_box = self.bbox;
         self.has_box
     }
+
+    /// Rotations keep solid angles, so the object's own density applies
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        self.object.pdf_value(&self.to_object(o), &self.to_object(v))
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        self.to_world(&self.object.random(&self.to_object(o)))
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        let first = emitters.len();
+        self.object.emitters(emitters);
+        for emitter in &mut emitters[first..] {
+            emitter.object = Arc::new(RotateY::new(emitter.object.clone(), self.angle));
+        }
+    }
 }
 
 pub struct FlipFace {
@@ -200,5 +249,20 @@ impl Hittable for FlipFace {
     fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut AABB) -> bool {
         self.object.bounding_box(t0, t1, output_box)
     }
-}
 
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        self.object.pdf_value(o, v)
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        self.object.random(o)
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        let first = emitters.len();
+        self.object.emitters(emitters);
+        for emitter in &mut emitters[first..] {
+            emitter.object = Arc::new(FlipFace::new(emitter.object.clone()));
+        }
+    }
+}
@@ -2,9 +2,10 @@ use crate::hittable::{Hittable, HitRecord};
 use crate::ray::Ray;
 use crate::aabb::AABB;
 use crate::vec3::Vec3;
+use crate::util;
+use crate::lights::Emitter;
 
 use std::sync::Arc;
-use crate::util::random_int_range;
 use rand::prelude::SliceRandom;
 
 #[derive(Clone)]
@@ -12,6 +13,12 @@ pub struct HittableList {
     pub objects: Vec<Arc<dyn Hittable>>
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        HittableList::new()
+    }
+}
+
 impl HittableList {
     pub fn new() -> Self {
         HittableList { objects: vec![] }
@@ -54,8 +61,8 @@ impl Hittable for HittableList {
         let mut first_box = true;
 
         for object in &self.objects {
-            if object.bounding_box(t0, t1, &mut temp_box) { return false; }
-            *output_box = if first_box { temp_box.clone() } else { output_box.surrounding_box(&temp_box) };
+            if !object.bounding_box(t0, t1, &mut temp_box) { return false; }
+            *output_box = if first_box { temp_box } else { output_box.surrounding_box(&temp_box) };
             first_box = false;
         }
 
@@ -63,6 +70,10 @@ impl Hittable for HittableList {
     }
 
     fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
         let weight = 1.0 / self.objects.len() as f64;
 
         let mut sum = 0.0;
@@ -75,10 +86,15 @@ impl Hittable for HittableList {
     }
 
     fn random(&self, o: &Vec3) -> Vec3 {
-        let mut rng = rand::thread_rng();
-        match self.objects.choose(&mut rng) {
+        match util::with_rng(|rng| self.objects.choose(rng)) {
             None => { Vec3::random() }
             Some(s) => { s.random(o) }
         }
     }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        for object in &self.objects {
+            object.emitters(emitters);
+        }
+    }
 }
@@ -0,0 +1,279 @@
+//! The ways a path is continued from each surface it hits.
+
+use crate::background::Background;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::ScatterRecord;
+use crate::pdf::Pdf;
+use crate::ray::Ray;
+use crate::util;
+use crate::vec3::Vec3;
+use crate::{MAX_DEPTH, MIN_DEPTH};
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How the light arriving along a camera ray is estimated
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Integrator {
+    /// Samples the lights with a shadow ray at every diffuse surface and
+    /// combines it with a material sample, weighted by the power heuristic
+    /// https://graphics.stanford.edu/courses/cs348b-03/papers/veach-chapter9.pdf
+    #[default]
+    NextEvent,
+    /// Follows one direction per surface, picked half the time toward the
+    /// lights and half from the material
+    Mixture,
+}
+
+impl Integrator {
+    pub const NAMES: [&'static str; 2] = ["next-event", "mixture"];
+
+    /// The light arriving along `r` over paths no longer than `depth` allows
+    pub fn ray_color<T: Hittable>(self, r: &Ray, background: &dyn Background, world: &T,
+                                  lights: Option<&dyn Hittable>, depth: PathDepth) -> Vec3 {
+        let direct = DirectLighting::new(lights, background);
+        match self {
+            Integrator::NextEvent => next_event_ray_color(r, background, world, direct.as_ref(), depth),
+            Integrator::Mixture => mixture_ray_color(r, background, world, direct.as_ref(), depth),
+        }
+    }
+}
+
+/// How many times paths bounce
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PathDepth {
+    /// Paths end after this many bounces
+    pub max: usize,
+    /// Paths with this many bounces or more may be ended early by Russian
+    /// roulette, the less light they can still carry the sooner
+    pub min: usize,
+}
+
+impl Default for PathDepth {
+    fn default() -> Self {
+        PathDepth { max: MAX_DEPTH, min: MIN_DEPTH }
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = UnknownIntegratorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "next-event" => Ok(Integrator::NextEvent),
+            "mixture" => Ok(Integrator::Mixture),
+            _ => Err(UnknownIntegratorError { name: s.to_string() }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownIntegratorError {
+    name: String,
+}
+
+impl fmt::Display for UnknownIntegratorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown integrator '{}', valid integrators are: {}", self.name, Integrator::NAMES.join(", "))
+    }
+}
+
+impl std::error::Error for UnknownIntegratorError {}
+
+/// Picks directions from a point toward the lights half the time and toward
+/// the bright parts of the background the other half, or toward whichever
+/// of the two can be sampled
+struct DirectLighting<'a> {
+    lights: Option<&'a dyn Hittable>,
+    environment: Option<Arc<dyn Pdf>>,
+}
+
+impl<'a> DirectLighting<'a> {
+    /// `None` when there are neither lights nor a background worth sampling
+    fn new(lights: Option<&'a dyn Hittable>, background: &dyn Background) -> Option<Self> {
+        let environment = background.pdf();
+        if lights.is_none() && environment.is_none() {
+            None
+        } else {
+            Some(DirectLighting { lights, environment })
+        }
+    }
+
+    fn value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        match (self.lights, &self.environment) {
+            (Some(lights), Some(environment)) => 0.5 * lights.pdf_value(o, v) + 0.5 * environment.value(v),
+            (Some(lights), None) => lights.pdf_value(o, v),
+            (None, Some(environment)) => environment.value(v),
+            (None, None) => 0.0,
+        }
+    }
+
+    fn generate(&self, o: &Vec3) -> Vec3 {
+        match (self.lights, &self.environment) {
+            (Some(lights), Some(_)) if util::random_double() < 0.5 => lights.random(o),
+            (_, Some(environment)) => environment.generate(),
+            (Some(lights), None) => lights.random(o),
+            (None, None) => Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// The weight of a sample taken with density `f` when another strategy could
+/// have taken it with density `g`
+fn power_heuristic(f: f64, g: f64) -> f64 {
+    let (f2, g2) = (f * f, g * g);
+    if f2 + g2 > 0.0 {
+        f2 / (f2 + g2)
+    } else {
+        0.0
+    }
+}
+
+/// Ends paths with a chance that grows as the light they can carry shrinks,
+/// returning the throughput of a surviving path scaled up to make up for the
+/// ones that were ended
+fn russian_roulette(throughput: Vec3) -> Option<Vec3> {
+    let survival = throughput.x().max(throughput.y()).max(throughput.z()).min(0.95);
+    if util::random_double() < survival {
+        Some(throughput.div(survival))
+    } else {
+        None
+    }
+}
+
+/// The light given off by whatever `r` reaches first
+fn emitted_along<T: Hittable>(r: &Ray, background: &dyn Background, world: &T) -> Vec3 {
+    let mut rec = HitRecord::new();
+    if world.hit(r, 0.001, f64::INFINITY, &mut rec) {
+        rec.mat.emitted(r, &rec, rec.u, rec.v, &rec.p)
+    } else {
+        background.value(&r.direction())
+    }
+}
+
+fn next_event_ray_color<T: Hittable>(r: &Ray, background: &dyn Background, world: &T,
+                                     direct: Option<&DirectLighting>, depth: PathDepth) -> Vec3 {
+    let mut color = Vec3::zero();
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+    let mut ray = *r;
+    // The density the material picked `ray` with, when the lights were sampled
+    // at its origin too and light it finds counts only as much as they missed
+    let mut material_pdf_val = None;
+
+    for bounce in 0..depth.max {
+        let weight = match (material_pdf_val, direct) {
+            (Some(pdf_val), Some(direct)) => power_heuristic(pdf_val, direct.value(&ray.origin(), &ray.direction())),
+            _ => 1.0,
+        };
+
+        let mut rec = HitRecord::new();
+        if !world.hit(&ray, 0.001, f64::INFINITY, &mut rec) {
+            color = color + throughput * background.value(&ray.direction()).scale(weight);
+            break;
+        }
+
+        let mut srec = ScatterRecord::new();
+        color = color + throughput * rec.mat.emitted(&ray, &rec, rec.u, rec.v, &rec.p).scale(weight);
+
+        if !rec.mat.clone().scatter(&ray, &mut rec, &mut srec) {
+            break;
+        }
+
+        if srec.is_specular {
+            throughput = throughput * srec.attenuation;
+            ray = srec.specular_ray;
+            material_pdf_val = None;
+        } else {
+            let material_pdf = srec.pdf_ptr.unwrap();
+
+            // The shadow ray is one bounce longer, so it is only traced when the path may still grow
+            if let Some(direct) = direct.filter(|_| bounce + 1 < depth.max) {
+                let shadow = Ray::new(rec.p, direct.generate(&rec.p), r.time());
+                let light_pdf = direct.value(&rec.p, &shadow.direction());
+                let scattering_pdf = rec.mat.scattering_pdf(&ray, &rec, &shadow);
+
+                if light_pdf > 0.0 && scattering_pdf > 0.0 {
+                    let weight = power_heuristic(light_pdf, material_pdf.value(&shadow.direction()));
+                    color = color + (throughput * srec.attenuation * emitted_along(&shadow, background, world))
+                        .scale(scattering_pdf * weight / light_pdf);
+                }
+            }
+
+            let scattered = Ray::new(rec.p, material_pdf.generate(), r.time());
+            let pdf_val = material_pdf.value(&scattered.direction());
+            if pdf_val <= 0.0 {
+                break;
+            }
+
+            throughput = throughput * srec.attenuation.scale(rec.mat.scattering_pdf(&ray, &rec, &scattered) / pdf_val);
+            ray = scattered;
+            material_pdf_val = Some(pdf_val);
+        }
+
+        if bounce + 1 >= depth.min {
+            match russian_roulette(throughput) {
+                Some(survivor) => throughput = survivor,
+                None => break,
+            }
+        }
+    }
+
+    color
+}
+
+fn mixture_ray_color<T: Hittable>(r: &Ray, background: &dyn Background, world: &T,
+                                  direct: Option<&DirectLighting>, depth: PathDepth) -> Vec3 {
+    let mut color = Vec3::zero();
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+    let mut ray = *r;
+
+    for bounce in 0..depth.max {
+        let mut rec = HitRecord::new();
+        if !world.hit(&ray, 0.001, f64::INFINITY, &mut rec) {
+            color = color + throughput * background.value(&ray.direction());
+            break;
+        }
+
+        let mut srec = ScatterRecord::new();
+        color = color + throughput * rec.mat.emitted(&ray, &rec, rec.u, rec.v, &rec.p);
+
+        if !rec.mat.clone().scatter(&ray, &mut rec, &mut srec) {
+            break;
+        }
+
+        if srec.is_specular {
+            throughput = throughput * srec.attenuation;
+            ray = srec.specular_ray;
+        } else {
+            let material_pdf = srec.pdf_ptr.unwrap();
+            let (direction, pdf_val) = match direct {
+                Some(direct) => {
+                    let direction = if util::random_double() < 0.5 {
+                        direct.generate(&rec.p)
+                    } else {
+                        material_pdf.generate()
+                    };
+                    (direction, 0.5 * direct.value(&rec.p, &direction) + 0.5 * material_pdf.value(&direction))
+                }
+                None => {
+                    let direction = material_pdf.generate();
+                    (direction, material_pdf.value(&direction))
+                }
+            };
+
+            let scattered = Ray::new(rec.p, direction, r.time());
+            throughput = throughput * srec.attenuation.scale(rec.mat.scattering_pdf(&ray, &rec, &scattered) / pdf_val);
+            ray = scattered;
+        }
+
+        if bounce + 1 >= depth.min {
+            match russian_roulette(throughput) {
+                Some(survivor) => throughput = survivor,
+                None => break,
+            }
+        }
+    }
+
+    color
+}
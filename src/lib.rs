@@ -8,143 +8,164 @@ pub mod util;
 pub mod material;
 pub mod triangle;
 pub mod mesh;
+pub mod ply;
+pub mod stl;
 pub mod aabb;
 pub mod bvh;
+pub mod flat_bvh;
+pub mod triangle_mesh;
 pub mod texture;
 pub mod perlin;
 pub mod aarect;
 pub mod cube;
 pub mod constant_medium;
+pub mod transform;
+pub mod lights;
+pub mod background;
+pub mod integrator;
 pub mod scenes;
 pub mod onb;
 pub mod pdf;
+pub mod scene_file;
+pub mod gltf_scene;
+pub mod renderer;
+pub mod tonemap;
+pub mod tile;
 
 #[macro_use]
 extern crate lazy_static;
 
 use wasm_bindgen::prelude::*;
 
-use crate::hittable::{Hittable, HitRecord};
+use crate::hittable::Hittable;
+use crate::background::Background;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
-use crate::mesh::Mesh;
-use crate::material::{Lambertian, ScatterRecord};
-use std::sync::Arc;
 use std::error::Error;
-use indicatif::{ProgressBar, ProgressStyle};
-use rayon::prelude::*;
-use crate::pdf::{HittablePdf, MixturePdf, Pdf};
+use crate::integrator::{Integrator, PathDepth, UnknownIntegratorError};
+use crate::renderer::Renderer;
+use crate::tonemap::{ToneMapper, UnknownToneMapError};
 
-const MAX_DEPTH: usize = 50;
+pub const MAX_DEPTH: usize = 50;
 
-pub fn ray_color<T: Hittable>(r: &Ray, background_color: &Vec3, world: &T, lights: Arc<dyn Hittable>, depth: usize) -> Vec3 {
-    let mut rec = HitRecord::new();
+/// The number of bounces before paths may be ended by Russian roulette
+pub const MIN_DEPTH: usize = 3;
 
-    if depth == 0 {
-        return Vec3::zero();
-    }
+/// The light arriving along `r` as estimated by the default integrator
+pub fn ray_color<T: Hittable>(r: &Ray, background: &dyn Background, world: &T, lights: Option<&dyn Hittable>, depth: PathDepth) -> Vec3 {
+    Integrator::default().ray_color(r, background, world, lights, depth)
+}
 
-    if !world.hit(r, 0.001, f64::INFINITY, &mut rec) {
-        return *background_color;
-    }
 
-    let mut srec = ScatterRecord::new();
-    let emitted = rec.mat.emitted(r, &rec, rec.u, rec.v, &rec.p);
+pub fn render_scene(scene: &str, width: usize, height: usize, samples: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    //let dragon = Mesh::new_from_obj("obj_files/dragon_hq.obj", &Vec3::new(555.0/2.0, 0.0, 555.0/2.0), 250.0, false,
+    //                                Some(Arc::new(Lambertian::new(SolidTexture::new(0.5, 0.5, 0.5)))), BVHSplit::Sah)?;
+    //world.add(Arc::new(dragon));
 
-    if !rec.mat.clone().scatter(r, &mut rec, &mut srec) {
-        return emitted;
-    }
+    util::seed_random(0);
+    let scene = scenes::build_scene(scene, width, height)?;
 
-    if srec.is_specular {
-        return srec.attenuation * ray_color(&srec.specular_ray, background_color, world, lights, depth - 1);
-    }
+    Ok(render(scene, width, height, samples))
+}
 
-    let light = Arc::new(HittablePdf::new(rec.p, lights.clone()));
-    let p = Arc::new(MixturePdf::new(light, srec.pdf_ptr.unwrap()));
+pub fn render(scene: scenes::Scene, width: usize, height: usize, samples: usize) -> Vec<u8> {
+    eprintln!("Scene with {} objects.\n", scene.objects.objects.len());
+
+    let mut renderer = Renderer::new(scene, width, height);
+    renderer.render_pass(samples);
+    renderer.snapshot()
+}
 
-    let scattered = Ray::new(rec.p, p.generate(), r.time());
-    let pdf_val = p.value(&scattered.direction());
+/// Renders a built in scene without clamping samples and returns the linear
+/// radiance of each pixel as RGB floats, suitable for HDR image formats
+pub fn render_scene_linear(scene: &str, width: usize, height: usize, samples: usize) -> Result<Vec<f32>, Box<dyn Error>> {
+    util::seed_random(0);
+    let scene = scenes::build_scene(scene, width, height)?;
 
-    emitted + (srec.attenuation * ray_color(&scattered, background_color, world, lights, depth - 1))
-        .scale(rec.mat.scattering_pdf(r, &rec, &scattered) / pdf_val)
+    let mut renderer = Renderer::new(scene, width, height);
+    renderer.set_sample_clamp(None);
+    renderer.render_pass(samples);
+    Ok(renderer.linear_snapshot())
 }
 
+#[wasm_bindgen]
+pub fn render_image_array(scene: &str, width: usize, height: usize, samples: usize) -> Result<Box<[u8]>, JsValue> {
+    let colors = render_scene(scene, width, height, samples)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
 
-pub fn render_scene(scene: &str, width: usize, height: usize, samples: usize) -> Result<Vec<u8>, Box<dyn Error>> {
-    //let dragon = Mesh::new_from_obj("obj_files/dragon_hq.obj", &Vec3::new(555.0/2.0, 0.0, 555.0/2.0), 250.0, false,
-    //                                Arc::new(Lambertian::new(SolidTexture::new(0.5, 0.5, 0.5))))?;
-    //world.add(Arc::new(dragon));
+    Ok(colors.into_boxed_slice())
+}
 
-    let scene = scenes::SCENE_MAP.get(scene).unwrap()(width, height);
+/// A progressive renderer for the web, each call to `render_pass` refines the image
+#[wasm_bindgen(js_name = Renderer)]
+pub struct WebRenderer {
+    renderer: Renderer,
+}
 
-    eprintln!("Scene with {} objects.\n", scene.objects.objects.len());
+#[wasm_bindgen(js_class = Renderer)]
+impl WebRenderer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(scene: &str, width: usize, height: usize) -> Result<WebRenderer, JsValue> {
+        util::seed_random(0);
+        let scene = scenes::build_scene(scene, width, height)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
 
-    let mut positions = vec![0; 4 * width * height];
-
-    if cfg!(target_os = "linux") {
-        positions
-            .par_chunks_mut(4)
-            .enumerate()
-            .for_each(|(i, chunk)| {
-                let x = i % width;
-                let y = height - i / width;
-
-                let mut color = Vec3::zero();
-                for _ in 0..(samples) {
-                    let u = (x as f64 + util::random_double()) / width as f64;
-                    let v = (y as f64 + util::random_double()) / height as f64;
-                    let r = scene.camera.get_ray(u, v);
-                    let ray_color = ray_color(&r, &scene.background_color, &scene.objects, scene.lights.clone(), MAX_DEPTH);
-
-                    color = color + ray_color.min(1.0);
-                }
-                let rgb = color.return_color(samples);
-
-                chunk[0] = rgb.0;
-                chunk[1] = rgb.1;
-                chunk[2] = rgb.2;
-                chunk[3] = 255;
-            });
-    } else {
-        positions
-            .chunks_mut(4)
-            .enumerate()
-            .for_each(|(i, chunk)| {
-                let x = i % width;
-                let y = height - i / width;
-
-                let mut color = Vec3::zero();
-                for _ in 0..(samples) {
-                    let u = (x as f64 + util::random_double()) / width as f64;
-                    let v = (y as f64 + util::random_double()) / height as f64;
-                    let r = scene.camera.get_ray(u, v);
-                    let ray_color = ray_color(&r, &scene.background_color, &scene.objects, scene.lights.clone(), MAX_DEPTH);
-
-                    color = color + ray_color.min(1.0);
-                }
-                let rgb = color.return_color(samples);
-
-                chunk[0] = rgb.0;
-                chunk[1] = rgb.1;
-                chunk[2] = rgb.2;
-                chunk[3] = 255;
-            });
+        Ok(WebRenderer { renderer: Renderer::new(scene, width, height) })
     }
 
-    Ok(positions)
-}
+    /// Renders another pass, `progress` is called with the number of completed
+    /// pixels and the total number of pixels as the pass advances
+    pub fn render_pass(&mut self, samples: usize, progress: Option<js_sys::Function>) {
+        match progress {
+            Some(f) => self.renderer.render_pass_with_progress(samples, |done, total| {
+                let _ = f.call2(&JsValue::NULL, &JsValue::from(done as u32), &JsValue::from(total as u32));
+            }),
+            None => self.renderer.render_pass(samples),
+        }
+    }
 
-#[wasm_bindgen]
-pub fn render_image_array(scene: &str, width: usize, height: usize, samples: usize) -> Box<[u8]> {
-    let colors = render_scene(scene, width, height, samples).unwrap();
+    pub fn samples(&self) -> usize {
+        self.renderer.samples()
+    }
+
+    /// Selects one of "clamp", "reinhard", "reinhard-extended" or "aces" with an exposure in stops
+    pub fn set_tone_mapping(&mut self, tone_map: &str, exposure: f64) -> Result<(), JsValue> {
+        let tone_map = tone_map.parse().map_err(|e: UnknownToneMapError| JsValue::from_str(&e.to_string()))?;
+        self.renderer.set_tone_mapper(ToneMapper::new(tone_map, exposure));
+        Ok(())
+    }
 
-    colors.into_boxed_slice()
+    /// Selects "next-event", which samples lights with shadow rays, or "mixture"
+    pub fn set_integrator(&mut self, integrator: &str) -> Result<(), JsValue> {
+        let integrator = integrator.parse().map_err(|e: UnknownIntegratorError| JsValue::from_str(&e.to_string()))?;
+        self.renderer.set_integrator(integrator);
+        Ok(())
+    }
+
+    /// Sets the most bounces a path takes, fewer makes passes faster but darker
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.renderer.set_max_depth(depth);
+    }
+
+    /// Sets the number of bounces before paths may be ended by Russian roulette
+    pub fn set_min_depth(&mut self, depth: usize) {
+        self.renderer.set_min_depth(depth);
+    }
+
+    /// Stops clamping single samples, which keeps bright highlights unbiased but noisier
+    pub fn set_sample_clamp(&mut self, enabled: bool) {
+        self.renderer.set_sample_clamp(if enabled { Some(1.0) } else { None });
+    }
+
+    pub fn snapshot(&self) -> Box<[u8]> {
+        self.renderer.snapshot().into_boxed_slice()
+    }
 }
 
 #[wasm_bindgen]
 pub fn get_scenes() -> Box<[JsValue]> {
-    let keys = scenes::SCENE_MAP.keys();
-    let scenes: Vec<JsValue> = keys.map(|s| JsValue::from_str(s)).collect();
+    let names = scenes::scene_names();
+    let scenes: Vec<JsValue> = names.into_iter().map(JsValue::from_str).collect();
     scenes.into_boxed_slice()
 }
 
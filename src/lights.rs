@@ -0,0 +1,203 @@
+//! The set of objects sampled directly as lights.
+//!
+//! Objects report the parts of themselves with emissive materials through
+//! `Hittable::emitters`, so a scene's lights can be gathered from its objects
+//! instead of being listed by hand.
+
+use crate::hittable::{Hittable, HitRecord};
+use crate::ray::Ray;
+use crate::aabb::AABB;
+use crate::vec3::Vec3;
+use crate::util;
+
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// An emissive part of an object, placed in world space
+pub struct Emitter {
+    pub object: Arc<dyn Hittable>,
+    /// The surface area of the object
+    pub area: f64,
+    /// The light given off averaged over the surface
+    pub emission: Vec3,
+}
+
+/// How often each light is picked when sampling
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LightWeighting {
+    /// In proportion to the light's total power, its emission times its area
+    #[default]
+    Power,
+    /// In proportion to the light's area
+    Area,
+}
+
+impl LightWeighting {
+    pub const NAMES: [&'static str; 2] = ["power", "area"];
+
+    fn weight(self, emitter: &Emitter) -> f64 {
+        match self {
+            LightWeighting::Power => {
+                let e = emitter.emission;
+                emitter.area * (e.x() + e.y() + e.z()) / 3.0
+            }
+            LightWeighting::Area => emitter.area,
+        }
+    }
+}
+
+impl FromStr for LightWeighting {
+    type Err = UnknownLightWeightingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "power" => Ok(LightWeighting::Power),
+            "area" => Ok(LightWeighting::Area),
+            _ => Err(UnknownLightWeightingError { name: s.to_string() }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownLightWeightingError {
+    name: String,
+}
+
+impl fmt::Display for UnknownLightWeightingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown light weighting '{}', valid weightings are: {}", self.name, LightWeighting::NAMES.join(", "))
+    }
+}
+
+impl std::error::Error for UnknownLightWeightingError {}
+
+/// Objects sampled with a chance proportional to their weights
+#[derive(Clone, Default)]
+pub struct Lights {
+    objects: Vec<Arc<dyn Hittable>>,
+    /// Running total of the objects' weights
+    weight_cdf: Vec<f64>,
+}
+
+impl Lights {
+    pub fn new() -> Self {
+        Lights { objects: Vec::new(), weight_cdf: Vec::new() }
+    }
+
+    /// Gathers every emissive part of `world`. When the lights give off no
+    /// power at all they are weighted by area so they are still sampled.
+    pub fn from_objects(world: &dyn Hittable, weighting: LightWeighting) -> Self {
+        let mut emitters = Vec::new();
+        world.emitters(&mut emitters);
+
+        let total: f64 = emitters.iter().map(|e| weighting.weight(e)).sum();
+        let weighting = if total > 0.0 { weighting } else { LightWeighting::Area };
+
+        let mut lights = Lights::new();
+        for emitter in emitters {
+            let weight = weighting.weight(&emitter);
+            lights.add(emitter.object, weight);
+        }
+        lights
+    }
+
+    /// Adds `object` to be sampled `weight` times as often as a light with a weight of one
+    pub fn add(&mut self, object: Arc<dyn Hittable>, weight: f64) {
+        self.objects.push(object);
+        self.weight_cdf.push(self.total_weight() + weight.max(0.0));
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// The lights ready to be shared by a scene, or `None` when there are no lights
+    pub fn into_shared(self) -> Option<Arc<dyn Hittable>> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(Arc::new(self))
+        }
+    }
+
+    pub fn total_weight(&self) -> f64 {
+        self.weight_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    /// The chance of sampling the object at `index`
+    pub fn probability(&self, index: usize) -> f64 {
+        let previous = if index == 0 { 0.0 } else { self.weight_cdf[index - 1] };
+        (self.weight_cdf[index] - previous) / self.total_weight()
+    }
+}
+
+impl Hittable for Lights {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+
+        for object in &self.objects {
+            if object.hit(r, t_min, closest_so_far, rec) {
+                hit_anything = true;
+                closest_so_far = rec.t;
+            }
+        }
+
+        hit_anything
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut AABB) -> bool {
+        let mut surrounding = None;
+        let mut temp_box = AABB::new_max();
+
+        for object in &self.objects {
+            if !object.bounding_box(t0, t1, &mut temp_box) {
+                return false;
+            }
+            surrounding = Some(match surrounding {
+                Some(aabb_box) => temp_box.surrounding_box(&aabb_box),
+                None => temp_box,
+            });
+        }
+
+        match surrounding {
+            Some(aabb_box) => {
+                *output_box = aabb_box;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        let total = self.total_weight();
+        if total <= 0.0 {
+            return 0.0;
+        }
+
+        (0..self.objects.len())
+            .map(|i| self.probability(i))
+            .zip(&self.objects)
+            .filter(|&(p, _)| p > 0.0)
+            .map(|(p, object)| p * object.pdf_value(o, v))
+            .sum()
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        let total = self.total_weight();
+        if total <= 0.0 {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+
+        let target = util::random_double() * total;
+        let index = self.weight_cdf.partition_point(|&w| w <= target).min(self.objects.len() - 1);
+        self.objects[index].random(o)
+    }
+}
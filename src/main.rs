@@ -1,17 +1,68 @@
-use ray_tracer::{render_scene};
+use ray_tracer::background::EnvironmentMap;
+use ray_tracer::bvh::{BVHNode, BVHSplit};
+use ray_tracer::flat_bvh::FlatBVH;
+use ray_tracer::hittable::Hittable;
+use ray_tracer::hittable_list::HittableList;
+use ray_tracer::integrator::Integrator;
+use ray_tracer::lights::{Lights, LightWeighting};
+use ray_tracer::mesh::Mesh;
+use ray_tracer::renderer::Renderer;
+use ray_tracer::scene_file::load_scene_file;
+use ray_tracer::gltf_scene::load_gltf;
+use ray_tracer::scenes::{build_scene, scene_names};
+use ray_tracer::tonemap::{ToneMap, ToneMapper};
+use ray_tracer::tile::TileOrder;
+use ray_tracer::{util, MAX_DEPTH, MIN_DEPTH};
+use ray_tracer::vec3::Vec3;
 use std::{error::Error};
 use std::time::Instant;
 use indicatif::{ProgressBar, ProgressStyle};
 use clap::{App, Arg};
+use image::ImageFormat;
+use image::codecs::hdr::HdrEncoder;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+
+/// Writes the current image of the renderer to `file`, HDR formats get the
+/// linear radiance and all other formats get the tone mapped colors
+fn save(renderer: &Renderer, file: &str, format: ImageFormat) -> Result<(), Box<dyn Error>> {
+    let width = renderer.width() as u32;
+    let height = renderer.height() as u32;
+
+    match format {
+        ImageFormat::OpenExr => {
+            let image = image::Rgb32FImage::from_raw(width, height, renderer.linear_snapshot()).unwrap();
+            image.save_with_format(file, format)?;
+        }
+        ImageFormat::Hdr => {
+            let pixels: Vec<image::Rgb<f32>> = renderer.linear_snapshot()
+                .chunks(3)
+                .map(|p| image::Rgb([p[0], p[1], p[2]]))
+                .collect();
+            let writer = BufWriter::new(File::create(file)?);
+            HdrEncoder::new(writer).encode(&pixels, width as usize, height as usize)?;
+        }
+        _ => {
+            let image = image::RgbaImage::from_raw(width, height, renderer.snapshot()).unwrap();
+            image.save_with_format(file, format)?;
+        }
+    }
+
+    Ok(())
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
     const IMAGE_WIDTH: usize = 1200 / 2;
     const IMAGE_HEIGHT: usize = 800 / 2;
     const SAMPLES_PER_PIXEL: usize = 50;
+    const SCENE: &str = "Cornell Box with Metal Cube";
 
     let width_default = IMAGE_WIDTH.to_string();
     let height_default = IMAGE_HEIGHT.to_string();
     let samples_default = SAMPLES_PER_PIXEL.to_string();
+    let max_depth_default = MAX_DEPTH.to_string();
+    let min_depth_default = MIN_DEPTH.to_string();
 
     let matches = App::new("Ray Tracer")
         .version("0.1")
@@ -19,7 +70,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         .about("A small ray tracer based on Ray Tracing in a weekend")
         .arg(Arg::with_name("OUTPUT")
             .help("The file to output to with a file extension")
-            .required(true)
+            .required_unless("list-scenes")
             .index(1))
         .arg(Arg::with_name("width")
             .short("w")
@@ -39,38 +90,301 @@ fn main() -> Result<(), Box<dyn Error>> {
             .help("Sets the number of samples per pixel")
             .default_value(&samples_default)
             .takes_value(true))
+        .arg(Arg::with_name("scene")
+            .long("scene")
+            .help("Sets the built in scene to render")
+            .default_value(SCENE)
+            .takes_value(true))
+        .arg(Arg::with_name("scene-file")
+            .long("scene-file")
+            .value_name("PATH")
+            .help("Renders the scene described by a TOML scene file, or a glTF file ending in .gltf or .glb")
+            .conflicts_with("scene")
+            .takes_value(true))
+        .arg(Arg::with_name("mesh")
+            .long("mesh")
+            .value_name("PATH")
+            .help("Adds an OBJ, PLY or STL mesh to the scene")
+            .takes_value(true))
+        .arg(Arg::with_name("mesh-center")
+            .long("mesh-center")
+            .value_name("X,Y,Z")
+            .help("Sets where the mesh from --mesh is moved to")
+            .default_value("0,0,0")
+            .allow_hyphen_values(true)
+            .takes_value(true))
+        .arg(Arg::with_name("mesh-scale")
+            .long("mesh-scale")
+            .help("Sets how much the mesh from --mesh is scaled by")
+            .default_value("1.0")
+            .takes_value(true))
+        .arg(Arg::with_name("mesh-flat")
+            .long("mesh-flat")
+            .help("Flat shades the mesh from --mesh instead of using smooth normals"))
+        .arg(Arg::with_name("environment")
+            .long("environment")
+            .value_name("PATH")
+            .help("Lights the scene with an equirectangular HDR or EXR environment map in place of its background")
+            .takes_value(true))
+        .arg(Arg::with_name("environment-rotation")
+            .long("environment-rotation")
+            .value_name("DEGREES")
+            .help("Sets how far the environment map from --environment is turned about the vertical axis")
+            .default_value("0.0")
+            .allow_hyphen_values(true)
+            .takes_value(true))
+        .arg(Arg::with_name("environment-intensity")
+            .long("environment-intensity")
+            .help("Sets how much the environment map from --environment is brightened by")
+            .default_value("1.0")
+            .takes_value(true))
+        .arg(Arg::with_name("preview")
+            .long("preview")
+            .value_name("SAMPLES")
+            .help("Writes the image rendered so far to the output file every SAMPLES samples per pixel")
+            .takes_value(true))
+        .arg(Arg::with_name("tonemap")
+            .long("tonemap")
+            .help("Sets the tone mapping operator for LDR output")
+            .possible_values(&ToneMap::NAMES)
+            .default_value("clamp")
+            .takes_value(true))
+        .arg(Arg::with_name("white-point")
+            .long("white-point")
+            .help("Sets the luminance mapped to white by the reinhard-extended tone map")
+            .default_value("4.0")
+            .takes_value(true))
+        .arg(Arg::with_name("exposure")
+            .long("exposure")
+            .help("Sets the exposure adjustment in stops")
+            .default_value("0.0")
+            .allow_hyphen_values(true)
+            .takes_value(true))
+        .arg(Arg::with_name("integrator")
+            .long("integrator")
+            .help("Sets whether lights are sampled with shadow rays and multiple importance sampling or by a mixture with the material")
+            .possible_values(&Integrator::NAMES)
+            .default_value("next-event")
+            .takes_value(true))
+        .arg(Arg::with_name("max-depth")
+            .long("max-depth")
+            .help("Sets the most bounces a path takes")
+            .default_value(&max_depth_default)
+            .takes_value(true))
+        .arg(Arg::with_name("min-depth")
+            .long("min-depth")
+            .help("Sets the number of bounces before paths may be ended by Russian roulette")
+            .default_value(&min_depth_default)
+            .takes_value(true))
+        .arg(Arg::with_name("no-sample-clamp")
+            .long("no-sample-clamp")
+            .help("Stops clamping single samples to 1, keeping highlights unbiased at the cost of fireflies"))
+        .arg(Arg::with_name("threads")
+            .short("t")
+            .long("threads")
+            .help("Sets the number of render threads, defaults to one per core")
+            .takes_value(true))
+        .arg(Arg::with_name("tile-size")
+            .long("tile-size")
+            .help("Sets the width and height of render tiles in pixels")
+            .default_value("32")
+            .takes_value(true))
+        .arg(Arg::with_name("tile-order")
+            .long("tile-order")
+            .help("Sets the order tiles are rendered in")
+            .possible_values(&TileOrder::NAMES)
+            .default_value("spiral")
+            .takes_value(true))
+        .arg(Arg::with_name("bvh")
+            .long("bvh")
+            .help("Puts the scene's objects in a BVH built with the given split and prints its statistics")
+            .possible_values(&BVHSplit::NAMES)
+            .takes_value(true))
+        .arg(Arg::with_name("bvh-layout")
+            .long("bvh-layout")
+            .help("Sets whether the BVH from --bvh is a flat array of nodes or a tree of nodes")
+            .possible_values(&["flat", "tree"])
+            .default_value("flat")
+            .takes_value(true))
+        .arg(Arg::with_name("seed")
+            .long("seed")
+            .help("Sets the random seed, renders with the same seed and settings are identical")
+            .default_value("0")
+            .takes_value(true))
+        .arg(Arg::with_name("list-scenes")
+            .long("list-scenes")
+            .help("Lists the built in scenes and exits"))
         .get_matches();
 
+    if matches.is_present("list-scenes") {
+        for name in scene_names() {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
     let file = matches.value_of("OUTPUT").unwrap();
     let width = matches.value_of("width").unwrap().parse().unwrap_or(IMAGE_WIDTH);
     let height = matches.value_of("height").unwrap().parse().unwrap_or(IMAGE_HEIGHT);
     let samples = matches.value_of("samples").unwrap().parse().unwrap_or(SAMPLES_PER_PIXEL);
+    let exposure = matches.value_of("exposure").unwrap().parse()?;
+    let tone_map = match matches.value_of("tonemap").unwrap().parse()? {
+        ToneMap::ExtendedReinhard(_) => ToneMap::ExtendedReinhard(matches.value_of("white-point").unwrap().parse()?),
+        tone_map => tone_map,
+    };
+    let seed = matches.value_of("seed").unwrap().parse()?;
+    let tile_size = matches.value_of("tile-size").unwrap().parse()?;
+    let tile_order = matches.value_of("tile-order").unwrap().parse()?;
+    let integrator: Integrator = matches.value_of("integrator").unwrap().parse()?;
+    let max_depth = matches.value_of("max-depth").unwrap().parse()?;
+    let min_depth = matches.value_of("min-depth").unwrap().parse()?;
+    let preview = matches.value_of("preview").map(|p| p.parse::<usize>()).transpose()?.map(|p| p.max(1));
 
     eprintln!("Starting render.");
     eprintln!("Dimensions: {}x{}", width, height);
     eprintln!("Samples per Pixel: {}\n", samples);
 
-    image::ImageFormat::from_path(file)?;
+    let format = ImageFormat::from_path(file)?;
+    let hdr = format == ImageFormat::OpenExr || format == ImageFormat::Hdr;
 
     let time = Instant::now();
 
     eprintln!("Rendering scene...");
 
-    let total = height * width;
+    // Scenes use random numbers too, for the Perlin noise tables and BVH split axes
+    util::seed_random(seed);
+    let mut scene = match matches.value_of("scene-file") {
+        Some(path) if path.ends_with(".gltf") || path.ends_with(".glb") => match load_gltf(path, width, height) {
+            Ok(scene) => scene,
+            Err(e) => {
+                eprintln!("Error in glTF file {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        Some(path) => match load_scene_file(path, width, height) {
+            Ok(scene) => scene,
+            Err(e) => {
+                eprintln!("Error in scene file {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => match build_scene(matches.value_of("scene").unwrap(), width, height) {
+            Ok(scene) => {
+                for path in &scene.missing_images {
+                    eprintln!("Could not load '{}', using a plain color instead.", path);
+                }
+                scene
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+    };
+
+    if let Some(path) = matches.value_of("mesh") {
+        let center: Vec<f64> = matches.value_of("mesh-center").unwrap()
+            .split(',')
+            .map(|c| c.trim().parse())
+            .collect::<Result<_, _>>()?;
+        if center.len() != 3 {
+            return Err("--mesh-center needs three comma separated numbers".into());
+        }
+        let scale = matches.value_of("mesh-scale").unwrap().parse()?;
+
+        // Meshes have many triangles, so the SAH is worth its longer build
+        match Mesh::open(path, &Vec3::new(center[0], center[1], center[2]), scale, matches.is_present("mesh-flat"),
+                         None, BVHSplit::Sah) {
+            Ok(mesh) => {
+                let mut emitters = Vec::new();
+                mesh.emitters(&mut emitters);
+                scene.objects.add(Arc::new(mesh));
+                // A mesh with emissive materials lights the scene too
+                if !emitters.is_empty() {
+                    scene.lights = Lights::from_objects(&scene.objects, LightWeighting::Power).into_shared();
+                }
+            }
+            Err(e) => {
+                eprintln!("Error in mesh {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(path) = matches.value_of("environment") {
+        let rotation = matches.value_of("environment-rotation").unwrap().parse()?;
+        let intensity = matches.value_of("environment-intensity").unwrap().parse()?;
+        match EnvironmentMap::open(path, rotation, intensity) {
+            Ok(environment) => scene.background = Arc::new(environment),
+            Err(e) => {
+                eprintln!("Error in environment map {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    eprintln!("Scene with {} objects.\n", scene.objects.objects.len());
+
+    if let Some(split) = matches.value_of("bvh") {
+        let bvh_time = Instant::now();
+        let layout = matches.value_of("bvh-layout").unwrap();
+        let (bvh, stats): (Arc<dyn Hittable>, _) = if layout == "tree" {
+            let bvh = BVHNode::from_list_with_split(&mut scene.objects, 0.0, 1.0, split.parse()?);
+            let stats = bvh.stats();
+            (Arc::new(bvh), stats)
+        } else {
+            let bvh = FlatBVH::from_list(&scene.objects, 0.0, 1.0, split.parse()?);
+            let stats = bvh.stats();
+            (Arc::new(bvh), stats)
+        };
+        eprintln!("Built {} {} BVH in {} ms: {}.\n", layout, split, bvh_time.elapsed().as_millis(), stats);
+
+        let mut objects = HittableList::new();
+        objects.add(bvh);
+        scene.objects = objects;
+    }
+
+    // Progress is measured in pixel samples so passes of any size add up
+    let total = width * height * samples;
     let render_bar = ProgressBar::new(total as u64);
     render_bar.set_style(ProgressStyle::default_bar()
         .template("{wide_bar} {percent}% Elapsed: {elapsed_precise} Remaining: {eta_precise}"));
     render_bar.set_draw_delta((total / 1000) as u64);
 
-    let colors = render_scene("Cornell Box with Metal Cube", width, height, samples)?;
+    let mut renderer = Renderer::new(scene, width, height);
+    renderer.set_tone_mapper(ToneMapper::new(tone_map, exposure));
+    renderer.set_seed(seed);
+    renderer.set_tile_size(tile_size);
+    renderer.set_tile_order(tile_order);
+    renderer.set_integrator(integrator);
+    renderer.set_max_depth(max_depth);
+    renderer.set_min_depth(min_depth);
+    if let Some(threads) = matches.value_of("threads") {
+        renderer.set_threads(threads.parse()?)?;
+    }
+    if hdr || matches.is_present("no-sample-clamp") {
+        renderer.set_sample_clamp(None);
+    }
+    let pass_samples = preview.unwrap_or(samples);
+
+    while renderer.samples() < samples {
+        let finished = renderer.samples() * width * height;
+        let pass = pass_samples.min(samples - renderer.samples());
+        renderer.render_pass_with_progress(pass, |pixels, _| {
+            render_bar.set_position((finished + pixels * pass) as u64);
+        });
+
+        if preview.is_some() && renderer.samples() < samples {
+            save(&renderer, file, format)?;
+            eprintln!("Preview with {} samples per pixel written to {}.", renderer.samples(), file);
+        }
+    }
 
     render_bar.finish();
 
     eprintln!("\nOutputting to {}.", file);
 
-    let image = image::RgbaImage::from_raw(width as u32, height as u32, colors).unwrap();
-
-    image.save(file)?;
+    save(&renderer, file, format)?;
 
     eprintln!("\nDone in {}.{:03} sec", time.elapsed().as_secs(), time.elapsed().subsec_millis());
 
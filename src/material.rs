@@ -1,8 +1,8 @@
 use crate::ray::Ray;
 use crate::hittable::HitRecord;
 use crate::vec3::Vec3;
-use crate::util::random_double;
 use crate::texture::Texture;
+use crate::util::{self, random_double};
 
 use std::f64;
 use std::sync::Arc;
@@ -16,6 +16,12 @@ pub struct ScatterRecord {
     pub pdf_ptr: Option<Arc<dyn Pdf>>,
 }
 
+impl Default for ScatterRecord {
+    fn default() -> Self {
+        ScatterRecord::new()
+    }
+}
+
 impl ScatterRecord {
     pub fn new() -> Self {
         ScatterRecord {
@@ -39,6 +45,18 @@ pub trait Material: Sync + Send {
     fn emitted(&self, _r_in: &Ray, _rec: &HitRecord, _u: f64, _v: f64, _point: &Vec3) -> Vec3 {
         Vec3::zero()
     }
+
+    /// Whether surfaces with this material give off light, which makes them
+    /// worth sampling directly
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// The light given off averaged over the surface, used to weigh how
+    /// often each light is sampled
+    fn average_emission(&self) -> Vec3 {
+        Vec3::zero()
+    }
 }
 
 /// https://en.wikipedia.org/wiki/Schlick%27s_approximation
@@ -66,14 +84,14 @@ impl<T> Lambertian<T>
 
 impl<T> Material for Lambertian<T>
     where T: Texture {
-    fn scatter(&self, r_in: &Ray, rec: &mut HitRecord, scatter_record: &mut ScatterRecord) -> bool {
+    fn scatter(&self, _r_in: &Ray, rec: &mut HitRecord, scatter_record: &mut ScatterRecord) -> bool {
         scatter_record.is_specular = false;
         scatter_record.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
         scatter_record.pdf_ptr = Some(Arc::new(pdf::CosinePdf::new(&rec.normal)));
         true
     }
 
-    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
         let cosine = rec.normal.dot(&scattered.direction().unit_vector());
         if cosine < 0.0 {
             0.0
@@ -126,32 +144,30 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &mut HitRecord, _scatter_record: &mut ScatterRecord) -> bool {
-        // *albedo = Vec3::new(1.0, 1.0, 1.0);
-        // let etai_over_etat = if rec.front_face {
-        //     1.0 / self.ref_idx
-        // } else {
-        //     self.ref_idx
-        // };
-        // let unit_direction = r_in.direction().unit_vector();
-        // let cos_theta = 1.0_f64.min((-unit_direction).dot(&rec.normal));
-        // let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        //
-        // if etai_over_etat * sin_theta > 1.0 {
-        //     let reflected = Vec3::reflect(&unit_direction, &rec.normal);
-        //     *scattered = Ray::new(rec.p, reflected, r_in.time());
-        //     return true;
-        // }
-        // let reflect_prob = schlick(cos_theta, etai_over_etat);
-        // if random_double() < reflect_prob {
-        //     let reflected = Vec3::reflect(&unit_direction, &rec.normal);
-        //     *scattered = Ray::new(rec.p, reflected, r_in.time());
-        //     return true;
-        // }
-        // let refracted = Vec3::refract(&unit_direction, &rec.normal, etai_over_etat);
-        // *scattered = Ray::new(rec.p, refracted, r_in.time());
-        // true
-        false
+    fn scatter(&self, r_in: &Ray, rec: &mut HitRecord, scatter_record: &mut ScatterRecord) -> bool {
+        scatter_record.is_specular = true;
+        scatter_record.pdf_ptr = None;
+        scatter_record.attenuation = Vec3::new(1.0, 1.0, 1.0);
+
+        let etai_over_etat = if rec.front_face {
+            1.0 / self.ref_idx
+        } else {
+            self.ref_idx
+        };
+        let unit_direction = r_in.direction().unit_vector();
+        let cos_theta = 1.0_f64.min((-unit_direction).dot(&rec.normal));
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        // Total internal reflection, otherwise reflect with the probability given by the Fresnel term
+        let direction = if etai_over_etat * sin_theta > 1.0 ||
+            random_double() < schlick(cos_theta, etai_over_etat) {
+            Vec3::reflect(&unit_direction, &rec.normal)
+        } else {
+            Vec3::refract(&unit_direction, &rec.normal, etai_over_etat)
+        };
+
+        scatter_record.specular_ray = Ray::new(rec.p, direction, r_in.time());
+        true
     }
 }
 
@@ -181,6 +197,24 @@ impl<T> Material for DiffuseLight<T>
             Vec3::zero()
         }
     }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    /// The mean of the texture over a grid of texture coordinates
+    fn average_emission(&self) -> Vec3 {
+        const STEPS: usize = 8;
+        let mut sum = Vec3::zero();
+        for i in 0..STEPS {
+            for j in 0..STEPS {
+                let u = (i as f64 + 0.5) / STEPS as f64;
+                let v = (j as f64 + 0.5) / STEPS as f64;
+                sum = sum + self.emit.value(u, v, &Vec3::zero());
+            }
+        }
+        sum.div((STEPS * STEPS) as f64)
+    }
 }
 
 #[derive(Clone)]
@@ -202,10 +236,46 @@ impl<T> Isotropic<T>
 
 impl<T> Material for Isotropic<T>
     where T: Texture {
-    fn scatter(&self, r_in: &Ray, rec: &mut HitRecord, _scatter_record: &mut ScatterRecord) -> bool {
-        // *scattered = Ray::new(rec.p, Vec3::random_in_unit_sphere(), r_in.time());
-        // *albedo = self.albedo.value(rec.u, rec.v, &rec.p);
-        // true
-        false
+    fn scatter(&self, _r_in: &Ray, rec: &mut HitRecord, scatter_record: &mut ScatterRecord) -> bool {
+        scatter_record.is_specular = false;
+        scatter_record.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        scatter_record.pdf_ptr = Some(Arc::new(pdf::SpherePdf::new()));
+        true
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * f64::consts::PI)
+    }
+}
+
+/// An anisotropic phase function for participating media
+/// https://en.wikipedia.org/wiki/Henyey%E2%80%93Greenstein_phase_function
+#[derive(Clone)]
+pub struct HenyeyGreenstein<T>
+    where T: Texture {
+    albedo: T,
+    g: f64,
+}
+
+impl<T> HenyeyGreenstein<T>
+    where T: Texture {
+    /// `g` is the mean cosine of the scattering angle and must be in (-1, 1)
+    pub fn new(albedo: T, g: f64) -> Self {
+        HenyeyGreenstein { albedo, g: util::clamp(g, -0.999, 0.999) }
     }
-}
\ No newline at end of file
+}
+
+impl<T> Material for HenyeyGreenstein<T>
+    where T: Texture {
+    fn scatter(&self, r_in: &Ray, rec: &mut HitRecord, scatter_record: &mut ScatterRecord) -> bool {
+        scatter_record.is_specular = false;
+        scatter_record.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        scatter_record.pdf_ptr = Some(Arc::new(pdf::HenyeyGreensteinPdf::new(&r_in.direction(), self.g)));
+        true
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, _rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = r_in.direction().unit_vector().dot(&scattered.direction().unit_vector());
+        pdf::henyey_greenstein(cos_theta, self.g)
+    }
+}
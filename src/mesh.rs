@@ -1,90 +1,301 @@
-use crate::hittable_list::HittableList;
-use crate::triangle::Triangle;
-use crate::material::{Material};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::texture::{ImageTexture, SolidTexture};
 use crate::vec3::Vec3;
 use crate::hittable::{Hittable, HitRecord};
 use crate::ray::Ray;
 use crate::aabb::AABB;
-use crate::bvh::BVHNode;
+use crate::bvh::{BVHSplit, BVHStats};
+use crate::triangle_mesh::{TriangleMesh, TriangleMeshData};
+use crate::lights::Emitter;
+use crate::{ply, stl};
 
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
 use std::sync::Arc;
 
+#[derive(Debug)]
+pub enum MeshError {
+    Io(std::io::Error),
+    Obj(tobj::LoadError),
+    /// A PLY or STL file that could not be read
+    Parse(String),
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::Io(e) => write!(f, "{}", e),
+            MeshError::Obj(e) => write!(f, "{}", e),
+            MeshError::Parse(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+impl From<std::io::Error> for MeshError {
+    fn from(e: std::io::Error) -> Self {
+        MeshError::Io(e)
+    }
+}
+
+impl From<tobj::LoadError> for MeshError {
+    fn from(e: tobj::LoadError) -> Self {
+        MeshError::Obj(e)
+    }
+}
+
 pub struct Mesh {
     name: String,
-    list: BVHNode,
+    list: TriangleMesh,
 }
 
 impl Mesh {
-    pub fn new_from_obj(path: &str, center: &Vec3, scale: f64, flat: bool, material: Arc<dyn Material>) -> Result<Self, tobj::LoadError> {
-        let (models, _materials) = tobj::load_obj(path)?;
+    /// Loads an OBJ, PLY or STL file picked by the extension of `path`, see
+    /// `new_from_obj` for the arguments
+    pub fn open(path: &str, center: &Vec3, scale: f64, flat: bool, material: Option<Arc<dyn Material>>,
+                split: BVHSplit) -> Result<Self, MeshError> {
+        let extension = Path::new(path).extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "ply" => Mesh::new_from_ply(path, center, scale, flat, material, split),
+            "stl" => Mesh::new_from_stl(path, center, scale, flat, material, split),
+            _ => Mesh::new_from_obj(path, center, scale, flat, material, split),
+        }
+    }
 
-        let name = models[0].name.clone();
+    /// Loads the triangles of an OBJ file, scaled and moved to `center`, into a BVH built with `split`.
+    /// Every face uses `material` if one is given, otherwise faces use the materials from the OBJ's MTL files.
+    pub fn new_from_obj(path: &str, center: &Vec3, scale: f64, flat: bool, material: Option<Arc<dyn Material>>,
+                        split: BVHSplit) -> Result<Self, MeshError> {
+        let (name, data) = Mesh::obj_data(path, center, scale, flat, material)?;
+        Ok(Mesh::build(name, data, split))
+    }
+
+    /// Loads the name and triangles of an OBJ file without building a BVH,
+    /// taking the same arguments as `new_from_obj`
+    pub fn obj_data(path: &str, center: &Vec3, scale: f64, flat: bool, material: Option<Arc<dyn Material>>)
+                    -> Result<(String, TriangleMeshData), MeshError> {
+        let (models, materials) = tobj::load_obj(path)?;
+
+        // An OBJ without any faces still parses, possibly into no models at all
+        let name = match models.first() {
+            Some(model) if models.iter().any(|m| !m.mesh.indices.is_empty()) => model.name.clone(),
+            _ => return Err(MeshError::Parse(format!("'{}' has no faces", path))),
+        };
         eprintln!("Importing mesh '{}'...", &name);
 
-        let mut list = HittableList::new_with_capacity(models[0].mesh.indices.len() / 3);
+        // Normals are only used when every model has them, models without
+        // texture coordinates get zeros when other models have them
+        let smooth = !flat && models.iter().all(|m| !m.mesh.normals.is_empty());
+        let textured = models.iter().any(|m| !m.mesh.texcoords.is_empty());
 
-        for (_, m) in models.iter().enumerate() {
+        let mut data = TriangleMeshData::default();
+        let default_material = match material {
+            Some(material) => {
+                data.materials.push(material);
+                None
+            }
+            None => {
+                let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+                data.materials.extend(materials.iter().map(|m| mtl_material(m, directory)));
+                data.materials.push(Arc::new(Lambertian::new(SolidTexture::new(0.5, 0.5, 0.5))));
+                Some(data.materials.len() as u32 - 1)
+            }
+        };
+
+        for m in models.iter() {
             let mesh = &m.mesh;
+            let offset = data.positions.len() as u32;
+
+            data.positions.extend(mesh.positions.chunks(3)
+                .map(|p| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64).scale(scale) + *center));
+            if smooth {
+                data.normals.extend(mesh.normals.chunks(3)
+                    .map(|n| Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64)));
+            }
+            if textured && mesh.texcoords.is_empty() {
+                data.uvs.extend(std::iter::repeat_n((0.0, 0.0), mesh.positions.len() / 3));
+            } else if textured {
+                data.uvs.extend(mesh.texcoords.chunks(2).map(|t| (t[0] as f64, t[1] as f64)));
+            }
+
+            data.triangles.extend(mesh.indices.chunks(3)
+                .map(|f| [f[0] + offset, f[1] + offset, f[2] + offset]));
+            if let Some(default_material) = default_material {
+                let id = mesh.material_id.map_or(default_material, |id| id as u32);
+                data.material_ids.extend(std::iter::repeat_n(id, mesh.indices.len() / 3));
+            }
+        }
+
+        Ok((name, data))
+    }
+
+    /// Loads the triangles of a PLY file like `new_from_obj`. Without a
+    /// `material` faces are colored by their vertices' colors if the file has
+    /// them. Files without normals get smooth normals unless `flat` is set.
+    pub fn new_from_ply(path: &str, center: &Vec3, scale: f64, flat: bool, material: Option<Arc<dyn Material>>,
+                        split: BVHSplit) -> Result<Self, MeshError> {
+        let name = file_name(path);
+        eprintln!("Importing mesh '{}'...", &name);
 
-            for f in 0..mesh.indices.len() / 3 {
-                let p1i = mesh.indices[3 * f] as usize;
-                let p2i = mesh.indices[3 * f + 1] as usize;
-                let p3i = mesh.indices[3 * f + 2] as usize;
-                let p1 = Vec3::new(
-                    mesh.positions[3 * p1i] as f64,
-                    mesh.positions[3 * p1i + 1] as f64,
-                    mesh.positions[3 * p1i + 2] as f64);
-                let p2 = Vec3::new(
-                    mesh.positions[3 * p2i] as f64,
-                    mesh.positions[3 * p2i + 1] as f64,
-                    mesh.positions[3 * p2i + 2] as f64);
-                let p3 = Vec3::new(
-                    mesh.positions[3 * p3i] as f64,
-                    mesh.positions[3 * p3i + 1] as f64,
-                    mesh.positions[3 * p3i + 2] as f64);
-
-                if !mesh.normals.is_empty() && !flat {
-                    let n1 = Vec3::new(
-                        mesh.normals[3 * p1i] as f64,
-                        mesh.normals[3 * p1i + 1] as f64,
-                        mesh.normals[3 * p1i + 2] as f64);
-                    let n2 = Vec3::new(
-                        mesh.normals[3 * p2i] as f64,
-                        mesh.normals[3 * p2i + 1] as f64,
-                        mesh.normals[3 * p2i + 2] as f64);
-                    let n3 = Vec3::new(
-                        mesh.normals[3 * p3i] as f64,
-                        mesh.normals[3 * p3i + 1] as f64,
-                        mesh.normals[3 * p3i + 2] as f64);
-
-                    list.add(Arc::new(Triangle::new_smooth(
-                        p1.scale(scale) + *center,
-                        p2.scale(scale) + *center,
-                        p3.scale(scale) + *center,
-                        n1,
-                        n2,
-                        n3,
-                        material.clone(),
-                    )))
-                } else {
-                    list.add(Arc::new(Triangle::new_flat(
-                        p1.scale(scale) + *center,
-                        p2.scale(scale) + *center,
-                        p3.scale(scale) + *center,
-                        material.clone(),
-                    )))
+        let ply = ply::parse(&std::fs::read(path)?)?;
+
+        let mut data = TriangleMeshData {
+            positions: ply.positions.iter().map(|p| p.scale(scale) + *center).collect(),
+            ..Default::default()
+        };
+        if !flat {
+            data.normals = if ply.normals.is_empty() {
+                smooth_normals(&data.positions, &ply.triangles)
+            } else {
+                ply.normals.iter().map(Vec3::unit_vector).collect()
+            };
+        }
+
+        match material {
+            Some(material) => data.materials.push(material),
+            None if !ply.colors.is_empty() => {
+                // Each face gets the average color of its vertices, faces with the same 8 bit color share a material
+                let mut ids: HashMap<[u8; 3], u32> = HashMap::new();
+                for &[a, b, c] in ply.triangles.iter() {
+                    let color = (ply.colors[a as usize] + ply.colors[b as usize] + ply.colors[c as usize]).div(3.0);
+                    let key = [color.x(), color.y(), color.z()].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                    let materials = &mut data.materials;
+                    let id = *ids.entry(key).or_insert_with(|| {
+                        let [r, g, b] = key.map(|c| c as f64 / 255.0);
+                        materials.push(Arc::new(Lambertian::new(SolidTexture::new(r, g, b))));
+                        materials.len() as u32 - 1
+                    });
+                    data.material_ids.push(id);
                 }
             }
+            None => data.materials.push(Arc::new(Lambertian::new(SolidTexture::new(0.5, 0.5, 0.5)))),
         }
 
-        eprintln!("Mesh '{}' imported with {} faces.", &name, list.objects.len());
+        data.triangles = ply.triangles;
+
+        Ok(Mesh::build(name, data, split))
+    }
 
-        let mesh = Mesh { name, list: BVHNode::from_list(&mut list, 0.0, 0.0) };
+    /// Loads the triangles of an ASCII or binary STL file like `new_from_obj`,
+    /// every face uses `material` or a gray Lambertian. STL files only store
+    /// face normals, so smooth normals are made unless `flat` is set.
+    pub fn new_from_stl(path: &str, center: &Vec3, scale: f64, flat: bool, material: Option<Arc<dyn Material>>,
+                        split: BVHSplit) -> Result<Self, MeshError> {
+        let name = file_name(path);
+        eprintln!("Importing mesh '{}'...", &name);
 
-        eprintln!("Mesh '{}' bounded.", &mesh.name);
+        let stl = stl::parse(&std::fs::read(path)?)?;
 
-        Ok(mesh)
+        let mut data = TriangleMeshData {
+            positions: stl.positions.iter().map(|p| p.scale(scale) + *center).collect(),
+            ..Default::default()
+        };
+        if !flat {
+            data.normals = smooth_normals(&data.positions, &stl.triangles);
+        }
+        data.triangles = stl.triangles;
+        data.materials.push(material.unwrap_or_else(|| Arc::new(Lambertian::new(SolidTexture::new(0.5, 0.5, 0.5)))));
+
+        Ok(Mesh::build(name, data, split))
+    }
+
+    /// Builds the mesh's BVH and reports its size
+    fn build(name: String, data: TriangleMeshData, split: BVHSplit) -> Self {
+        eprintln!("Mesh '{}' imported with {} faces.", &name, data.triangles.len());
+
+        let mesh = Mesh { name, list: TriangleMesh::new(data, split) };
+
+        eprintln!("Mesh '{}' bounded with {}, using {:.1} MB.", &mesh.name, mesh.list.bvh_stats(),
+                  mesh.memory_usage() as f64 / 1_000_000.0);
+
+        mesh
+    }
+
+    pub fn bvh_stats(&self) -> BVHStats {
+        self.list.bvh_stats()
+    }
+
+    /// The memory used by the mesh's vertices, triangles and BVH in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.list.memory_usage()
+    }
+}
+
+/// The name of a mesh file without its directory or extension
+fn file_name(path: &str) -> String {
+    Path::new(path).file_stem().map_or_else(|| path.to_string(), |stem| stem.to_string_lossy().into_owned())
+}
+
+/// Vertex normals averaged from the normals of the faces around each vertex,
+/// weighted by the faces' areas
+fn smooth_normals(positions: &[Vec3], triangles: &[[u32; 3]]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::zero(); positions.len()];
+    for &[a, b, c] in triangles {
+        let (a, b, c) = (a as usize, b as usize, c as usize);
+        // The cross product's length is twice the area
+        let normal = (positions[b] - positions[a]).cross(&(positions[c] - positions[a]));
+        normals[a] = normals[a] + normal;
+        normals[b] = normals[b] + normal;
+        normals[c] = normals[c] + normal;
+    }
+    normals.iter()
+        .map(|n| if n.length_squared() > 0.0 { n.unit_vector() } else { Vec3::new(0.0, 1.0, 0.0) })
+        .collect()
+}
+
+/// Picks the closest of the crate's materials to an MTL material.
+///
+/// An emissive color (`Ke`) makes a light, transparency (`d` below 1) makes
+/// glass with the index of refraction `Ni`, a specular color (`Ks`) brighter
+/// than the diffuse color (`Kd`) makes a metal whose fuzz comes from the
+/// shininess `Ns`, and anything else is Lambertian with `map_Kd` as its texture.
+/// http://paulbourke.net/dataformats/mtl/
+fn mtl_material(material: &tobj::Material, directory: &Path) -> Arc<dyn Material> {
+    let color = |c: [f32; 3]| Vec3::new(c[0] as f64, c[1] as f64, c[2] as f64);
+    let brightest = |c: Vec3| c.x().max(c.y()).max(c.z());
+
+    let emission = material.unknown_param.get("Ke")
+        .and_then(|ke| {
+            let channels: Vec<f64> = ke.split_whitespace().filter_map(|c| c.parse().ok()).collect();
+            match channels.len() {
+                3 => Some(Vec3::new(channels[0], channels[1], channels[2])),
+                1 => Some(Vec3::new(channels[0], channels[0], channels[0])),
+                _ => None,
+            }
+        })
+        .unwrap_or_else(Vec3::zero);
+    let diffuse = color(material.diffuse);
+    let specular = color(material.specular);
+
+    if brightest(emission) > 0.0 {
+        return Arc::new(DiffuseLight::new(SolidTexture::from(emission)));
+    }
+
+    if material.dissolve < 1.0 {
+        let ref_idx = if material.optical_density > 1.0 { material.optical_density as f64 } else { 1.5 };
+        return Arc::new(Dielectric::new(ref_idx));
+    }
+
+    if brightest(specular) > brightest(diffuse) {
+        // Roughness of a Beckmann distribution matching a Phong exponent
+        let fuzz = (2.0 / (material.shininess as f64 + 2.0)).sqrt();
+        return Arc::new(Metal::new(&specular, fuzz));
+    }
+
+    if !material.diffuse_texture.is_empty() {
+        let texture_path = directory.join(&material.diffuse_texture);
+        match ImageTexture::open(&texture_path.to_string_lossy()) {
+            Ok(texture) => return Arc::new(Lambertian::new(texture)),
+            Err(e) => eprintln!("Could not load texture '{}' of material '{}': {}",
+                                texture_path.display(), material.name, e),
+        }
     }
+
+    Arc::new(Lambertian::new(SolidTexture::from(diffuse)))
 }
 
 impl Hittable for Mesh {
@@ -95,4 +306,16 @@ impl Hittable for Mesh {
     fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut AABB) -> bool {
         self.list.bounding_box(t0, t1, output_box)
     }
+
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        self.list.pdf_value(o, v)
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        self.list.random(o)
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        self.list.emitters(emitters)
+    }
 }
@@ -4,6 +4,12 @@ pub struct Onb {
     axis: [Vec3; 3],
 }
 
+impl Default for Onb {
+    fn default() -> Self {
+        Onb::new()
+    }
+}
+
 impl Onb {
     pub fn new() -> Onb {
         Onb { axis: [Vec3::zero(); 3] }
@@ -29,6 +35,8 @@ impl Onb {
         self.u().scale(a.x()) + self.v().scale(a.y()) + self.w().scale(a.z())
     }
 
+    /// An orthonormal basis with `w` along `n`, so the local z axis used by
+    /// direction sampling points along `n`
     pub fn build_from_w(n: &Vec3) -> Onb {
         let w = n.unit_vector();
         let a = if w.x().abs() > 0.9 {
@@ -39,6 +47,6 @@ impl Onb {
         let v = w.cross(&a).unit_vector();
         let u = w.cross(&v);
 
-        Onb { axis: [w, v, u] }
+        Onb { axis: [u, v, w] }
     }
 }
\ No newline at end of file
@@ -4,8 +4,9 @@ use std::f64;
 use crate::util;
 use std::sync::Arc;
 use crate::hittable::Hittable;
+use crate::background;
 
-pub trait Pdf {
+pub trait Pdf: Sync + Send {
     fn value(&self, _direction: &Vec3) -> f64;
     fn generate(&self) -> Vec3;
 }
@@ -35,6 +36,71 @@ impl Pdf for CosinePdf {
     }
 }
 
+/// Uniform density over all directions
+#[derive(Default)]
+pub struct SpherePdf;
+
+impl SpherePdf {
+    pub fn new() -> Self {
+        SpherePdf
+    }
+}
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * f64::consts::PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        Vec3::random_unit_vector()
+    }
+}
+
+/// The Henyey-Greenstein phase function for an angle `theta` between the
+/// incoming and scattered directions. Positive values of `g` favour forward
+/// scattering, negative values favour back scattering and zero is isotropic.
+/// https://www.astro.umd.edu/~jph/HG_note.pdf
+pub fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * f64::consts::PI * denom * denom.sqrt())
+}
+
+/// Samples scattered directions around the direction of travel `w`
+/// following the Henyey-Greenstein phase function
+pub struct HenyeyGreensteinPdf {
+    uvw: Onb,
+    g: f64,
+}
+
+impl HenyeyGreensteinPdf {
+    pub fn new(w: &Vec3, g: f64) -> Self {
+        HenyeyGreensteinPdf { uvw: Onb::build_from_w(w), g }
+    }
+}
+
+impl Pdf for HenyeyGreensteinPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        henyey_greenstein(direction.unit_vector().dot(&self.uvw.w()), self.g)
+    }
+
+    fn generate(&self) -> Vec3 {
+        let r1 = util::random_double();
+        let r2 = util::random_double();
+        let g = self.g;
+
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * r1
+        } else {
+            let sqr_term = (1.0 - g * g) / (1.0 - g + 2.0 * g * r1);
+            (1.0 + g * g - sqr_term * sqr_term) / (2.0 * g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+        let phi = 2.0 * f64::consts::PI * r2;
+        self.uvw.local(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+    }
+}
+
 pub struct HittablePdf {
     o: Vec3,
     ptr: Arc<dyn Hittable>,
@@ -78,4 +144,74 @@ impl Pdf for MixturePdf {
             self.p[1].generate()
         }
     }
-}
\ No newline at end of file
+}
+
+/// Samples directions of an equirectangular environment map by picking a
+/// pixel in proportion to its weight, then a uniform point inside it
+pub struct EnvironmentPdf {
+    width: usize,
+    height: usize,
+    /// Radians the map is turned counterclockwise about +y
+    rotation: f64,
+    /// Running total of the rows' weights
+    row_cdf: Vec<f64>,
+    /// Running total of the weights along each row, one row after another
+    column_cdf: Vec<f64>,
+}
+
+impl EnvironmentPdf {
+    /// `weights` holds one weight per pixel, row by row from the top. Pixels
+    /// are picked uniformly when every weight is zero.
+    pub fn new(width: usize, height: usize, weights: &[f64], rotation: f64) -> Self {
+        assert_eq!(weights.len(), width * height, "need one weight per pixel");
+        let uniform = !weights.iter().any(|&w| w > 0.0);
+
+        let mut row_cdf = Vec::with_capacity(height);
+        let mut column_cdf = Vec::with_capacity(weights.len());
+        for row in weights.chunks(width) {
+            let mut total = 0.0;
+            for &weight in row {
+                total += if uniform { 1.0 } else { weight.max(0.0) };
+                column_cdf.push(total);
+            }
+            row_cdf.push(row_cdf.last().copied().unwrap_or(0.0) + total);
+        }
+
+        EnvironmentPdf { width, height, rotation, row_cdf, column_cdf }
+    }
+
+    fn pixel_probability(&self, i: usize, j: usize) -> f64 {
+        let row = &self.column_cdf[j * self.width..(j + 1) * self.width];
+        let previous = if i == 0 { 0.0 } else { row[i - 1] };
+        (row[i] - previous) / self.row_cdf[self.height - 1]
+    }
+}
+
+impl Pdf for EnvironmentPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cos_latitude = (1.0 - direction.unit_vector().y().powi(2)).max(0.0).sqrt();
+        if cos_latitude <= 0.0 {
+            return 0.0;
+        }
+
+        let (u, v) = background::direction_to_uv(direction, self.rotation);
+        let (i, j) = background::uv_to_pixel(u, v, self.width, self.height);
+        // Each pixel covers 1 / (width * height) of the map, and a unit of the
+        // map covers 2π² cos(latitude) steradians
+        let pixels = (self.width * self.height) as f64;
+        self.pixel_probability(i, j) * pixels / (2.0 * f64::consts::PI * f64::consts::PI * cos_latitude)
+    }
+
+    fn generate(&self) -> Vec3 {
+        let target = util::random_double() * self.row_cdf[self.height - 1];
+        let j = self.row_cdf.partition_point(|&total| total <= target).min(self.height - 1);
+
+        let row = &self.column_cdf[j * self.width..(j + 1) * self.width];
+        let target = util::random_double() * row[self.width - 1];
+        let i = row.partition_point(|&total| total <= target).min(self.width - 1);
+
+        let u = (i as f64 + util::random_double()) / self.width as f64;
+        let v = 1.0 - (j as f64 + util::random_double()) / self.height as f64;
+        background::uv_to_direction(u, v, self.rotation)
+    }
+}
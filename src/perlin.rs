@@ -11,6 +11,12 @@ pub struct Perlin {
     perm_z: [i32; POINT_COUNT],
 }
 
+impl Default for Perlin {
+    fn default() -> Self {
+        Perlin::new()
+    }
+}
+
 impl Perlin {
     pub fn new() -> Self {
         let mut perlin = Perlin {
@@ -31,6 +37,7 @@ impl Perlin {
         perlin
     }
 
+    #[allow(clippy::needless_range_loop)]
     pub fn noise(&self, point: &Vec3) -> f64 {
         let u = point.x() - point.x().floor();
         let v = point.y() - point.y().floor();
@@ -90,6 +97,7 @@ impl Perlin {
         }
     }
 
+    #[allow(clippy::needless_range_loop)]
     fn triliner_interp(c: [[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
         let uu = u * u * (3.0 - 2.0 * u);
         let vv = v * v * (3.0 - 2.0 * v);
@@ -0,0 +1,295 @@
+//! Reader for the triangles of PLY files, in ASCII or binary of either endianness.
+//!
+//! Only the `vertex` and `face` elements are used, other elements are read
+//! and skipped. Faces with more than three vertices are split into a fan.
+//! http://paulbourke.net/dataformats/ply/
+
+use crate::mesh::MeshError;
+use crate::vec3::Vec3;
+
+/// The geometry read from a PLY file
+pub(crate) struct PlyData {
+    pub positions: Vec<Vec3>,
+    /// Either empty or one normal per position
+    pub normals: Vec<Vec3>,
+    /// Either empty or one color per position, with channels from 0 to 1
+    pub colors: Vec<Vec3>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+enum Property {
+    Scalar(Scalar, String),
+    /// A count followed by that many items
+    List(Scalar, Scalar, String),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self, MeshError> {
+        match name {
+            "char" | "int8" => Ok(Scalar::I8),
+            "uchar" | "uint8" => Ok(Scalar::U8),
+            "short" | "int16" => Ok(Scalar::I16),
+            "ushort" | "uint16" => Ok(Scalar::U16),
+            "int" | "int32" => Ok(Scalar::I32),
+            "uint" | "uint32" => Ok(Scalar::U32),
+            "float" | "float32" => Ok(Scalar::F32),
+            "double" | "float64" => Ok(Scalar::F64),
+            _ => Err(MeshError::Parse(format!("unknown PLY property type '{}'", name))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// The value of a full color channel, integer colors go from zero to their largest value
+    fn full_color(self) -> f64 {
+        match self {
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            Scalar::I8 => i8::MAX as f64,
+            Scalar::I16 => i16::MAX as f64,
+            Scalar::I32 => i32::MAX as f64,
+            Scalar::U32 => u32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+}
+
+/// The body of the file after the header
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { data: &'a [u8], big_endian: bool },
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, MeshError> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(unexpected_end)?;
+                token.parse().map_err(|_| MeshError::Parse(format!("invalid PLY number '{}'", token)))
+            }
+            Body::Binary { data, big_endian } => {
+                let size = scalar.size();
+                if data.len() < size {
+                    return Err(unexpected_end());
+                }
+                let mut bytes = [0; 8];
+                bytes[..size].copy_from_slice(&data[..size]);
+                if *big_endian {
+                    bytes[..size].reverse();
+                }
+                *data = &data[size..];
+
+                Ok(match scalar {
+                    Scalar::I8 => bytes[0] as i8 as f64,
+                    Scalar::U8 => bytes[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(bytes),
+                })
+            }
+        }
+    }
+}
+
+fn unexpected_end() -> MeshError {
+    MeshError::Parse("unexpected end of PLY file".to_string())
+}
+
+/// Reads the vertices and faces of the PLY file in `bytes`
+pub(crate) fn parse(bytes: &[u8]) -> Result<PlyData, MeshError> {
+    let (format, elements, body) = parse_header(bytes)?;
+
+    let mut body = match format {
+        Format::Ascii => Body::Ascii(std::str::from_utf8(body)
+            .map_err(|_| MeshError::Parse("ASCII PLY body is not valid text".to_string()))?
+            .split_ascii_whitespace()),
+        _ => Body::Binary { data: body, big_endian: format == Format::BinaryBigEndian },
+    };
+
+    let mut data = PlyData { positions: Vec::new(), normals: Vec::new(), colors: Vec::new(), triangles: Vec::new() };
+
+    for element in elements.iter() {
+        match element.name.as_str() {
+            "vertex" => read_vertices(element, &mut body, &mut data)?,
+            "face" => read_faces(element, &mut body, &mut data)?,
+            _ => for _ in 0..element.count {
+                for property in element.properties.iter() {
+                    read_property(property, &mut body, |_| ())?;
+                }
+            },
+        }
+    }
+
+    let vertex_count = data.positions.len() as u32;
+    if data.triangles.iter().flatten().any(|&i| i >= vertex_count) {
+        return Err(MeshError::Parse("PLY face refers to a missing vertex".to_string()));
+    }
+
+    Ok(data)
+}
+
+fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, &[u8]), MeshError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut rest = bytes;
+    let mut first = true;
+
+    loop {
+        let end = rest.iter().position(|&b| b == b'\n')
+            .ok_or_else(|| MeshError::Parse("PLY header has no end_header".to_string()))?;
+        let line = String::from_utf8_lossy(&rest[..end]);
+        rest = &rest[end + 1..];
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if first {
+            if words != ["ply"] {
+                return Err(MeshError::Parse("not a PLY file".to_string()));
+            }
+            first = false;
+            continue;
+        }
+
+        match words.as_slice() {
+            ["format", name, _version] => format = Some(match *name {
+                "ascii" => Format::Ascii,
+                "binary_little_endian" => Format::BinaryLittleEndian,
+                "binary_big_endian" => Format::BinaryBigEndian,
+                _ => return Err(MeshError::Parse(format!("unknown PLY format '{}'", name))),
+            }),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| MeshError::Parse(format!("invalid PLY element count '{}'", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => elements.last_mut()
+                .ok_or_else(|| MeshError::Parse("PLY property before any element".to_string()))?
+                .properties.push(Property::List(Scalar::parse(count)?, Scalar::parse(item)?, name.to_string())),
+            ["property", scalar, name] => elements.last_mut()
+                .ok_or_else(|| MeshError::Parse("PLY property before any element".to_string()))?
+                .properties.push(Property::Scalar(Scalar::parse(scalar)?, name.to_string())),
+            ["end_header"] => break,
+            // Comments, obj_info and blank lines
+            _ => (),
+        }
+    }
+
+    let format = format.ok_or_else(|| MeshError::Parse("PLY header has no format".to_string()))?;
+    Ok((format, elements, rest))
+}
+
+/// Reads one property, calling `value` with each of its values
+fn read_property<F: FnMut(f64)>(property: &Property, body: &mut Body, mut value: F) -> Result<(), MeshError> {
+    match property {
+        Property::Scalar(scalar, _) => value(body.read(*scalar)?),
+        Property::List(count, item, _) => {
+            let count = body.read(*count)? as usize;
+            for _ in 0..count {
+                value(body.read(*item)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_vertices(element: &Element, body: &mut Body, data: &mut PlyData) -> Result<(), MeshError> {
+    let index = |name: &str| element.properties.iter()
+        .position(|p| matches!(p, Property::Scalar(_, n) if n == name));
+    let position = [index("x"), index("y"), index("z")];
+    let normal = [index("nx"), index("ny"), index("nz")];
+    let color = [index("red"), index("green"), index("blue")];
+
+    if position.iter().any(Option::is_none) {
+        return Err(MeshError::Parse("PLY vertices have no x, y and z".to_string()));
+    }
+    let has_normals = normal.iter().all(Option::is_some);
+    let has_colors = color.iter().all(Option::is_some);
+
+    let full_color = color.map(|i| match i.map(|i| &element.properties[i]) {
+        Some(Property::Scalar(scalar, _)) => scalar.full_color(),
+        _ => 1.0,
+    });
+
+    let mut values = vec![0.0; element.properties.len()];
+    for _ in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            read_property(property, body, |v| values[i] = v)?;
+        }
+
+        let vector = |indices: [Option<usize>; 3]| {
+            let [x, y, z] = indices.map(|i| values[i.unwrap()]);
+            Vec3::new(x, y, z)
+        };
+        data.positions.push(vector(position));
+        if has_normals {
+            data.normals.push(vector(normal));
+        }
+        if has_colors {
+            let c = vector(color);
+            data.colors.push(Vec3::new(c.x() / full_color[0], c.y() / full_color[1], c.z() / full_color[2]));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_faces(element: &Element, body: &mut Body, data: &mut PlyData) -> Result<(), MeshError> {
+    let indices = element.properties.iter()
+        .position(|p| matches!(p, Property::List(_, _, n) if n == "vertex_indices" || n == "vertex_index"))
+        .ok_or_else(|| MeshError::Parse("PLY faces have no vertex_indices".to_string()))?;
+
+    let mut face = Vec::new();
+    for _ in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            if i == indices {
+                face.clear();
+                read_property(property, body, |v| face.push(v))?;
+            } else {
+                read_property(property, body, |_| ())?;
+            }
+        }
+
+        if let Some(v) = face.iter().find(|v| !(0.0..=u32::MAX as f64).contains(*v) || v.fract() != 0.0) {
+            return Err(MeshError::Parse(format!("invalid PLY vertex index '{}'", v)));
+        }
+        for i in 2..face.len() {
+            data.triangles.push([face[0] as u32, face[i - 1] as u32, face[i] as u32]);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use crate::integrator::{Integrator, PathDepth};
+use crate::scenes::Scene;
+use crate::tile::{self, Tile, TileOrder};
+use crate::tonemap::ToneMapper;
+use crate::vec3::Vec3;
+use crate::util;
+
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+
+/// Renders a scene progressively by accumulating samples over several passes.
+///
+/// Each call to `render_pass` adds more samples to every pixel, and
+/// `snapshot` can be called between passes to get the image so far.
+pub struct Renderer {
+    scene: Scene,
+    width: usize,
+    height: usize,
+    accumulator: Vec<Vec3>,
+    samples: usize,
+    sample_clamp: Option<f64>,
+    integrator: Integrator,
+    depth: PathDepth,
+    tone_mapper: ToneMapper,
+    tile_size: usize,
+    tile_order: TileOrder,
+    pool: Option<ThreadPool>,
+    seed: u64,
+}
+
+impl Renderer {
+    pub fn new(scene: Scene, width: usize, height: usize) -> Self {
+        Renderer {
+            scene,
+            width,
+            height,
+            accumulator: vec![Vec3::zero(); width * height],
+            samples: 0,
+            sample_clamp: Some(1.0),
+            integrator: Integrator::default(),
+            depth: PathDepth::default(),
+            tone_mapper: ToneMapper::default(),
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            pool: None,
+            seed: 0,
+        }
+    }
+
+    /// Sets the seed every sample's random numbers are derived from. Each
+    /// sample gets its own stream based on its pixel and index, so renders
+    /// with the same seed are identical whatever the thread count or tiling.
+    /// Defaults to 0.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Sets the width and height in pixels of the tiles the image is split into. Defaults to 32.
+    pub fn set_tile_size(&mut self, tile_size: usize) {
+        self.tile_size = tile_size.max(1);
+    }
+
+    /// Sets the order tiles are rendered in. Defaults to `TileOrder::Spiral`.
+    pub fn set_tile_order(&mut self, tile_order: TileOrder) {
+        self.tile_order = tile_order;
+    }
+
+    /// Limits rendering to `threads` threads, by default rayon's global pool is used
+    pub fn set_threads(&mut self, threads: usize) -> Result<(), ThreadPoolBuildError> {
+        self.pool = Some(ThreadPoolBuilder::new().num_threads(threads).build()?);
+        Ok(())
+    }
+
+    /// Sets how `snapshot` converts radiance to displayable colors
+    pub fn set_tone_mapper(&mut self, tone_mapper: ToneMapper) {
+        self.tone_mapper = tone_mapper;
+    }
+
+    /// Sets the maximum value of each color channel of a single sample, which
+    /// reduces fireflies at the cost of dynamic range. `None` keeps the full
+    /// radiance as needed for HDR output. Defaults to `Some(1.0)`.
+    pub fn set_sample_clamp(&mut self, sample_clamp: Option<f64>) {
+        self.sample_clamp = sample_clamp;
+    }
+
+    /// Sets how the light arriving along each camera ray is estimated. Defaults to `Integrator::NextEvent`.
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+    }
+
+    /// Sets the most bounces a path takes. Defaults to `MAX_DEPTH`.
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.depth.max = depth;
+    }
+
+    /// Sets the number of bounces before paths may be ended by Russian
+    /// roulette, raising it lowers noise in dim corners at the cost of time.
+    /// Defaults to `MIN_DEPTH`.
+    pub fn set_min_depth(&mut self, depth: usize) {
+        self.depth.min = depth;
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The number of samples per pixel accumulated so far
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Adds `samples` more samples to every pixel
+    pub fn render_pass(&mut self, samples: usize) {
+        self.render_pass_with_progress(samples, |_, _| {});
+    }
+
+    /// Adds `samples` more samples to every pixel, calling `progress` with the
+    /// number of completed pixels and the total number of pixels as tiles finish
+    pub fn render_pass_with_progress<F>(&mut self, samples: usize, mut progress: F)
+        where F: FnMut(usize, usize) {
+        let scene = &self.scene;
+        let pool = self.pool.as_ref();
+        let accumulator = &mut self.accumulator;
+        let width = self.width;
+        let height = self.height;
+        let sample_clamp = self.sample_clamp.unwrap_or(f64::INFINITY);
+        let integrator = self.integrator;
+        let depth = self.depth;
+        let seed = self.seed;
+        let first_sample = self.samples;
+
+        let render_tile = |tile: &Tile| {
+            let mut colors = Vec::with_capacity(tile.pixels());
+
+            for row in tile.y0..tile.y1 {
+                let y = height - row;
+
+                for x in tile.x0..tile.x1 {
+                    let mut color = Vec3::zero();
+                    let pixel_seed = util::mix_seed(seed, (row * width + x) as u64);
+
+                    for sample in first_sample..first_sample + samples {
+                        util::seed_random(util::mix_seed(pixel_seed, sample as u64));
+
+                        let u = (x as f64 + util::random_double()) / width as f64;
+                        let v = (y as f64 + util::random_double()) / height as f64;
+                        let r = scene.camera.get_ray(u, v);
+                        let ray_color = integrator.ray_color(&r, &*scene.background, &scene.objects, scene.lights.as_deref(), depth);
+
+                        color = color + ray_color.min(sample_clamp);
+                    }
+
+                    colors.push(color);
+                }
+            }
+
+            colors
+        };
+
+        let tiles = tile::tiles(width, height, self.tile_size, self.tile_order);
+        let total = width * height;
+        let mut done = 0;
+
+        let threads = pool.map_or_else(rayon::current_num_threads, |pool| pool.current_num_threads());
+
+        // Tiles are rendered in batches so progress can be reported from this thread
+        for batch in tiles.chunks(2 * threads) {
+            let render_batch = || batch.par_iter().map(render_tile).collect::<Vec<Vec<Vec3>>>();
+            let colors = match pool {
+                Some(pool) => pool.install(render_batch),
+                None => render_batch(),
+            };
+
+            for (tile, colors) in batch.iter().zip(colors) {
+                for (row, colors) in (tile.y0..tile.y1).zip(colors.chunks(tile.width())) {
+                    let start = row * width + tile.x0;
+                    for (acc, color) in accumulator[start..start + tile.width()].iter_mut().zip(colors) {
+                        *acc = *acc + *color;
+                    }
+                }
+                done += tile.pixels();
+            }
+
+            progress(done, total);
+        }
+
+        self.samples += samples;
+    }
+
+    /// The tone mapped image rendered so far as RGBA bytes
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut positions = vec![0; 4 * self.width * self.height];
+
+        if self.samples == 0 {
+            positions.chunks_mut(4).for_each(|chunk| chunk[3] = 255);
+            return positions;
+        }
+
+        for (chunk, color) in positions.chunks_mut(4).zip(&self.accumulator) {
+            let rgb = self.tone_mapper.map(&color.div(self.samples as f64));
+
+            chunk[0] = rgb.0;
+            chunk[1] = rgb.1;
+            chunk[2] = rgb.2;
+            chunk[3] = 255;
+        }
+
+        positions
+    }
+
+    /// The average linear radiance of each pixel so far as RGB floats
+    pub fn linear_snapshot(&self) -> Vec<f32> {
+        let scale = if self.samples == 0 { 0.0 } else { 1.0 / self.samples as f64 };
+
+        self.accumulator.iter()
+            .flat_map(|color| {
+                let color = color.scale(scale);
+                [color.x() as f32, color.y() as f32, color.z() as f32]
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,546 @@
+//! Loader for declarative TOML scene descriptions.
+//!
+//! A scene file describes the camera, the background, a table of named
+//! materials, the objects in the world and the objects used for light sampling.
+//! Objects reference a named material or declare one inline, only objects used
+//! for light sampling, the boundaries of media and meshes with their own
+//! materials may leave it out.
+//! Without a `lights` list every object with an emissive material is sampled
+//! as a light, picked by its power or, with `light_weighting = "area"`, its
+//! area.
+//!
+//! The background is `background_color`, or a `background` table that is a
+//! `solid` color, a `gradient` from `bottom` to `top`, or an `environment`
+//! map loaded from `path` with an optional `rotation` in degrees and
+//! `intensity`, such as
+//! `background = { type = "environment", path = "sky.hdr", rotation = 90.0 }`.
+//!
+//! ```toml
+//! background_color = [0.0, 0.0, 0.0]
+//!
+//! [camera]
+//! lookfrom = [278.0, 278.0, -800.0]
+//! lookat = [278.0, 278.0, 0.0]
+//! vfov = 40.0
+//!
+//! [materials.white]
+//! type = "lambertian"
+//! albedo = [0.73, 0.73, 0.73]
+//!
+//! [[objects]]
+//! type = "translate"
+//! offset = [265.0, 0.0, 295.0]
+//! object = { type = "rotate_y", angle = 15.0, object = { type = "cube", min = [0.0, 0.0, 0.0], max = [165.0, 330.0, 165.0], material = "white" } }
+//!
+//! [[lights]]
+//! type = "xz_rect"
+//! x0 = 213.0
+//! x1 = 343.0
+//! z0 = 227.0
+//! z1 = 332.0
+//! k = 554.0
+//! ```
+
+use crate::aarect::{XYRect, XZRect, YZRect};
+use crate::background::{Background, EnvironmentMap, GradientBackground, SolidBackground};
+use crate::bvh::{BVHNode, BVHSplit};
+use crate::flat_bvh::FlatBVH;
+use crate::camera::Camera;
+use crate::constant_medium::ConstantMedium;
+use crate::cube::Cube;
+use crate::hittable::{FlipFace, Hittable, RotateY, Translate};
+use crate::hittable_list::HittableList;
+use crate::lights::{Lights, LightWeighting};
+use crate::material::{Dielectric, DiffuseLight, HenyeyGreenstein, Isotropic, Lambertian, Material, Metal};
+use crate::mesh::{Mesh, MeshError};
+use crate::scenes::Scene;
+use crate::sphere::{MovingSphere, Sphere};
+use crate::transform::{SingularTransformError, Transform};
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidTexture, Texture};
+use crate::vec3::Vec3;
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    UnknownMaterial(String),
+    Mesh(String, MeshError),
+    Image(String, image::ImageError),
+    Transform(SingularTransformError),
+    /// An object outside `lights` and medium boundaries without a material
+    MissingMaterial,
+}
+
+impl SceneFileError {
+    /// The zero based line and column of a parse error, if known.
+    pub fn line_col(&self) -> Option<(usize, usize)> {
+        match self {
+            SceneFileError::Parse(e) => e.line_col(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io(e) => write!(f, "{}", e),
+            SceneFileError::Parse(e) => write!(f, "{}", e),
+            SceneFileError::UnknownMaterial(name) => write!(f, "unknown material '{}'", name),
+            SceneFileError::Mesh(path, e) => write!(f, "could not load mesh '{}': {}", path, e),
+            SceneFileError::Image(path, e) => write!(f, "could not load image '{}': {}", path, e),
+            SceneFileError::Transform(e) => write!(f, "invalid transform: {}", e),
+            SceneFileError::MissingMaterial => write!(f, "missing material, only lights and medium boundaries may leave it out"),
+        }
+    }
+}
+
+impl Error for SceneFileError {}
+
+impl From<std::io::Error> for SceneFileError {
+    fn from(e: std::io::Error) -> Self {
+        SceneFileError::Io(e)
+    }
+}
+
+impl From<SingularTransformError> for SceneFileError {
+    fn from(e: SingularTransformError) -> Self {
+        SceneFileError::Transform(e)
+    }
+}
+
+impl From<toml::de::Error> for SceneFileError {
+    fn from(e: toml::de::Error) -> Self {
+        SceneFileError::Parse(e)
+    }
+}
+
+/// Reads and builds the scene file at `path` for an image of the given size.
+pub fn load_scene_file(path: &str, width: usize, height: usize) -> Result<Scene, SceneFileError> {
+    let source = fs::read_to_string(path)?;
+    parse_scene(&source, width, height)
+}
+
+/// Builds a scene from the contents of a scene file.
+pub fn parse_scene(source: &str, width: usize, height: usize) -> Result<Scene, SceneFileError> {
+    let desc: SceneDesc = toml::from_str(source)?;
+    desc.build(width, height)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    camera: CameraDesc,
+    #[serde(default)]
+    background_color: [f64; 3],
+    /// Replaces `background_color` when given
+    background: Option<BackgroundDesc>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDesc>,
+    #[serde(default)]
+    objects: Vec<ObjectDesc>,
+    #[serde(default)]
+    lights: Vec<ObjectDesc>,
+    /// How the lights found in `objects` are weighted when `lights` is empty
+    #[serde(default)]
+    light_weighting: LightWeighting,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    #[serde(default = "default_vup")]
+    vup: [f64; 3],
+    vfov: f64,
+    #[serde(default)]
+    aperture: f64,
+    #[serde(default = "default_focus_dist")]
+    focus_dist: f64,
+    #[serde(default)]
+    time0: f64,
+    #[serde(default)]
+    time1: f64,
+}
+
+fn default_vup() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_focus_dist() -> f64 {
+    10.0
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_sky_bottom() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn default_sky_top() -> [f64; 3] {
+    [0.5, 0.7, 1.0]
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDesc {
+    Solid { color: [f64; 3] },
+    Gradient {
+        #[serde(default = "default_sky_bottom")]
+        bottom: [f64; 3],
+        #[serde(default = "default_sky_top")]
+        top: [f64; 3],
+    },
+    Environment {
+        path: String,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_scale")]
+        intensity: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureDesc {
+    Color([f64; 3]),
+    Texture(TextureKind),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureKind {
+    Solid { color: [f64; 3] },
+    Checker { even: Box<TextureDesc>, odd: Box<TextureDesc> },
+    Noise { scale: f64 },
+    Image { path: String },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian { albedo: TextureDesc },
+    Metal { albedo: [f64; 3], #[serde(default)] fuzz: f64 },
+    Dielectric { ref_idx: f64 },
+    DiffuseLight { emit: TextureDesc },
+    Isotropic { albedo: TextureDesc },
+    HenyeyGreenstein { albedo: TextureDesc, g: f64 },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MaterialRef {
+    Named(String),
+    Inline(MaterialDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: Option<MaterialRef>,
+    },
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        material: Option<MaterialRef>,
+    },
+    XyRect { x0: f64, x1: f64, y0: f64, y1: f64, k: f64, material: Option<MaterialRef> },
+    XzRect { x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: Option<MaterialRef> },
+    YzRect { y0: f64, y1: f64, z0: f64, z1: f64, k: f64, material: Option<MaterialRef> },
+    Cube {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: Option<MaterialRef>,
+    },
+    Mesh {
+        /// An OBJ, PLY or STL file
+        path: String,
+        #[serde(default)]
+        center: [f64; 3],
+        #[serde(default = "default_scale")]
+        scale: f64,
+        #[serde(default)]
+        flat: bool,
+        material: Option<MaterialRef>,
+        /// How the mesh's BVH is built, "median" or "sah"
+        #[serde(default)]
+        split: BVHSplit,
+    },
+    ConstantMedium {
+        /// Only bounds the medium, so it may leave out its material
+        boundary: Box<ObjectDesc>,
+        albedo: TextureDesc,
+        density: f64,
+        /// Anisotropy of the Henyey-Greenstein phase function, zero scatters isotropically
+        #[serde(default)]
+        g: f64,
+    },
+    Translate { offset: [f64; 3], object: Box<ObjectDesc> },
+    RotateY { angle: f64, object: Box<ObjectDesc> },
+    FlipFace { object: Box<ObjectDesc> },
+    /// The object moved by each step of `steps` in turn
+    Transform { steps: Vec<TransformStep>, object: Box<ObjectDesc> },
+    /// One copy of the object for each list of steps, sharing the object's
+    /// geometry, in a flat BVH
+    Instances {
+        instances: Vec<Vec<TransformStep>>,
+        object: Box<ObjectDesc>,
+        #[serde(default)]
+        split: BVHSplit,
+    },
+    Bvh {
+        objects: Vec<ObjectDesc>,
+        #[serde(default)]
+        split: BVHSplit,
+        #[serde(default)]
+        layout: BvhLayout,
+    },
+}
+
+/// One step of a `transform`, written as `{ translate = [x, y, z] }`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformStep {
+    Translate([f64; 3]),
+    /// Counterclockwise by `angle` degrees
+    Rotate { axis: [f64; 3], angle: f64 },
+    Scale([f64; 3]),
+    LookAt {
+        from: [f64; 3],
+        to: [f64; 3],
+        #[serde(default = "default_vup")]
+        up: [f64; 3],
+    },
+}
+
+impl TransformStep {
+    fn apply(&self, transform: Transform) -> Result<Transform, SceneFileError> {
+        Ok(match self {
+            TransformStep::Translate(offset) => transform.translate(vec3(*offset))?,
+            TransformStep::Rotate { axis, angle } => transform.rotate(vec3(*axis), *angle)?,
+            TransformStep::Scale(factors) => transform.scale(vec3(*factors))?,
+            TransformStep::LookAt { from, to, up } => transform.look_at(vec3(*from), vec3(*to), vec3(*up))?,
+        })
+    }
+
+    /// `object` moved by each of `steps` in turn
+    fn apply_all(steps: &[TransformStep], object: Arc<dyn Hittable>) -> Result<Arc<dyn Hittable>, SceneFileError> {
+        let transform = steps.iter().try_fold(Transform::new(object), |t, step| step.apply(t))?;
+        Ok(Arc::new(transform))
+    }
+}
+
+/// Whether a `bvh` object is a tree of `BVHNode`s or a `FlatBVH`
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum BvhLayout {
+    #[default]
+    Tree,
+    Flat,
+}
+
+/// A texture whose concrete type is only known once the scene file is read.
+#[derive(Clone)]
+enum FileTexture {
+    Solid(SolidTexture),
+    Checker(Box<CheckerTexture<FileTexture, FileTexture>>),
+    Noise(Box<NoiseTexture>),
+    Image(ImageTexture),
+}
+
+impl Texture for FileTexture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
+        match self {
+            FileTexture::Solid(t) => t.value(u, v, p),
+            FileTexture::Checker(t) => t.value(u, v, p),
+            FileTexture::Noise(t) => t.value(u, v, p),
+            FileTexture::Image(t) => t.value(u, v, p),
+        }
+    }
+}
+
+fn vec3(a: [f64; 3]) -> Vec3 {
+    Vec3::new(a[0], a[1], a[2])
+}
+
+impl SceneDesc {
+    fn build(self, width: usize, height: usize) -> Result<Scene, SceneFileError> {
+        let mut materials = HashMap::new();
+        for (name, desc) in &self.materials {
+            materials.insert(name.clone(), desc.build()?);
+        }
+
+        let mut objects = HittableList::new_with_capacity(self.objects.len());
+        for desc in &self.objects {
+            objects.add(desc.build(&materials, false)?);
+        }
+
+        let lights: Option<Arc<dyn Hittable>> = if self.lights.is_empty() {
+            Lights::from_objects(&objects, self.light_weighting).into_shared()
+        } else {
+            let mut lights = HittableList::new_with_capacity(self.lights.len());
+            for desc in &self.lights {
+                lights.add(desc.build(&materials, true)?);
+            }
+            Some(Arc::new(lights))
+        };
+
+        let background = match &self.background {
+            Some(desc) => desc.build()?,
+            None => Arc::new(SolidBackground::from(vec3(self.background_color))),
+        };
+
+        Ok(Scene {
+            objects,
+            camera: self.camera.build(width, height),
+            background,
+            lights,
+            missing_images: Vec::new(),
+        })
+    }
+}
+
+impl CameraDesc {
+    fn build(&self, width: usize, height: usize) -> Camera {
+        Camera::new_timed(
+            vec3(self.lookfrom),
+            vec3(self.lookat),
+            vec3(self.vup),
+            self.vfov,
+            width as f64 / height as f64,
+            self.aperture,
+            self.focus_dist,
+            self.time0,
+            self.time1,
+        )
+    }
+}
+
+impl BackgroundDesc {
+    fn build(&self) -> Result<Arc<dyn Background>, SceneFileError> {
+        Ok(match self {
+            BackgroundDesc::Solid { color } => Arc::new(SolidBackground::from(vec3(*color))),
+            BackgroundDesc::Gradient { bottom, top } => Arc::new(GradientBackground::new(vec3(*bottom), vec3(*top))),
+            BackgroundDesc::Environment { path, rotation, intensity } =>
+                Arc::new(EnvironmentMap::open(path, *rotation, *intensity)
+                    .map_err(|e| SceneFileError::Image(path.clone(), e))?),
+        })
+    }
+}
+
+impl TextureDesc {
+    fn build(&self) -> Result<FileTexture, SceneFileError> {
+        Ok(match self {
+            TextureDesc::Color(c) | TextureDesc::Texture(TextureKind::Solid { color: c }) =>
+                FileTexture::Solid(SolidTexture::from(vec3(*c))),
+            TextureDesc::Texture(TextureKind::Checker { even, odd }) =>
+                FileTexture::Checker(Box::new(CheckerTexture::new(even.build()?, odd.build()?))),
+            TextureDesc::Texture(TextureKind::Noise { scale }) =>
+                FileTexture::Noise(Box::new(NoiseTexture::new(*scale))),
+            TextureDesc::Texture(TextureKind::Image { path }) =>
+                FileTexture::Image(ImageTexture::open(path)
+                    .map_err(|e| SceneFileError::Image(path.clone(), e))?),
+        })
+    }
+}
+
+impl MaterialDesc {
+    fn build(&self) -> Result<Arc<dyn Material>, SceneFileError> {
+        Ok(match self {
+            MaterialDesc::Lambertian { albedo } => Arc::new(Lambertian::new(albedo.build()?)),
+            MaterialDesc::Metal { albedo, fuzz } => Arc::new(Metal::new(&vec3(*albedo), *fuzz)),
+            MaterialDesc::Dielectric { ref_idx } => Arc::new(Dielectric::new(*ref_idx)),
+            MaterialDesc::DiffuseLight { emit } => Arc::new(DiffuseLight::new(emit.build()?)),
+            MaterialDesc::Isotropic { albedo } => Arc::new(Isotropic::new(albedo.build()?)),
+            MaterialDesc::HenyeyGreenstein { albedo, g } => Arc::new(HenyeyGreenstein::new(albedo.build()?, *g)),
+        })
+    }
+}
+
+impl MaterialRef {
+    fn build(reference: &Option<MaterialRef>, materials: &HashMap<String, Arc<dyn Material>>,
+             light: bool) -> Result<Arc<dyn Material>, SceneFileError> {
+        match reference {
+            Some(MaterialRef::Named(name)) => materials.get(name)
+                .cloned()
+                .ok_or_else(|| SceneFileError::UnknownMaterial(name.clone())),
+            Some(MaterialRef::Inline(desc)) => desc.build(),
+            // Objects only used for light sampling or bounding a medium do not need a material
+            None if light => Ok(Arc::new(Lambertian::new(SolidTexture::from(Vec3::zero())))),
+            None => Err(SceneFileError::MissingMaterial),
+        }
+    }
+}
+
+impl ObjectDesc {
+    /// Objects in `lights` are only sampled, so when `light` is set they may
+    /// leave out their material, as may the boundaries of media
+    fn build(&self, materials: &HashMap<String, Arc<dyn Material>>,
+             light: bool) -> Result<Arc<dyn Hittable>, SceneFileError> {
+        Ok(match self {
+            ObjectDesc::Sphere { center, radius, material } =>
+                Arc::new(Sphere::new(&vec3(*center), *radius, MaterialRef::build(material, materials, light)?)),
+            ObjectDesc::MovingSphere { center0, center1, time0, time1, radius, material } =>
+                Arc::new(MovingSphere::new(&vec3(*center0), &vec3(*center1), *time0, *time1, *radius,
+                                           MaterialRef::build(material, materials, light)?)),
+            ObjectDesc::XyRect { x0, x1, y0, y1, k, material } =>
+                Arc::new(XYRect::new(MaterialRef::build(material, materials, light)?, *x0, *x1, *y0, *y1, *k)),
+            ObjectDesc::XzRect { x0, x1, z0, z1, k, material } =>
+                Arc::new(XZRect::new(MaterialRef::build(material, materials, light)?, *x0, *x1, *z0, *z1, *k)),
+            ObjectDesc::YzRect { y0, y1, z0, z1, k, material } =>
+                Arc::new(YZRect::new(MaterialRef::build(material, materials, light)?, *y0, *y1, *z0, *z1, *k)),
+            ObjectDesc::Cube { min, max, material } =>
+                Arc::new(Cube::new(vec3(*min), vec3(*max), MaterialRef::build(material, materials, light)?)),
+            ObjectDesc::Mesh { path, center, scale, flat, material, split } =>
+                // Without a material the mesh uses the materials from its MTL files or its vertex colors
+                Arc::new(Mesh::open(path, &vec3(*center), *scale, *flat,
+                                 material.as_ref().map(|_| MaterialRef::build(material, materials, light)).transpose()?, *split)
+                    .map_err(|e| SceneFileError::Mesh(path.clone(), e))?),
+            ObjectDesc::ConstantMedium { boundary, albedo, density, g } => if *g == 0.0 {
+                Arc::new(ConstantMedium::new(boundary.build(materials, true)?, albedo.build()?, *density))
+            } else {
+                Arc::new(ConstantMedium::new_anisotropic(boundary.build(materials, true)?, albedo.build()?, *density, *g))
+            },
+            ObjectDesc::Translate { offset, object } =>
+                Arc::new(Translate::new(object.build(materials, light)?, vec3(*offset))),
+            ObjectDesc::RotateY { angle, object } =>
+                Arc::new(RotateY::new(object.build(materials, light)?, *angle)),
+            ObjectDesc::FlipFace { object } =>
+                Arc::new(FlipFace::new(object.build(materials, light)?)),
+            ObjectDesc::Transform { steps, object } => TransformStep::apply_all(steps, object.build(materials, light)?)?,
+            ObjectDesc::Instances { instances, object, split } => {
+                let object = object.build(materials, light)?;
+                let instances = instances.iter()
+                    .map(|steps| TransformStep::apply_all(steps, object.clone()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Arc::new(FlatBVH::new(&instances, 0.0, 1.0, *split))
+            }
+            ObjectDesc::Bvh { objects, split, layout } => {
+                let mut list = HittableList::new_with_capacity(objects.len());
+                for desc in objects {
+                    list.add(desc.build(materials, light)?);
+                }
+                match layout {
+                    BvhLayout::Tree => Arc::new(BVHNode::from_list_with_split(&mut list, 0.0, 1.0, *split)),
+                    BvhLayout::Flat => Arc::new(FlatBVH::from_list(&list, 0.0, 1.0, *split)),
+                }
+            }
+        })
+    }
+}
@@ -1,6 +1,6 @@
 use crate::hittable_list::HittableList;
 use crate::sphere::{Sphere, MovingSphere};
-use crate::material::{Lambertian, Metal, Dielectric, DiffuseLight};
+use crate::material::{Lambertian, Metal, Dielectric, DiffuseLight, Material};
 use crate::texture::{SolidTexture, CheckerTexture, NoiseTexture, ImageTexture};
 use crate::util;
 use crate::vec3::Vec3;
@@ -10,28 +10,83 @@ use crate::cube::Cube;
 use crate::camera::Camera;
 use crate::constant_medium::ConstantMedium;
 use crate::bvh::BVHNode;
+use crate::lights::{Lights, LightWeighting};
+use crate::background::{Background, SolidBackground};
 
 use std::sync::Arc;
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 
 lazy_static! {
     pub static ref SCENE_MAP: HashMap<String, fn(usize, usize) -> Scene> = {
         let mut map = HashMap::new();
-        // map.insert("Book 1".to_string(), book1_scene as fn(usize, usize) -> Scene);
-        // map.insert("Book 2".to_string(), book2_scene as fn(usize, usize) -> Scene);
+        map.insert("Book 1".to_string(), book1_scene as fn(usize, usize) -> Scene);
+        map.insert("Book 2".to_string(), book2_scene as fn(usize, usize) -> Scene);
         map.insert("Cornell Box".to_string(), cornell_scene as fn(usize, usize) -> Scene);
         map.insert("Cornell Box with Cubes".to_string(), cornell_cubes_scene as fn(usize, usize) -> Scene);
         map.insert("Cornell Box with Metal Cube".to_string(), cornell_metal_cube_scene as fn(usize, usize) -> Scene);
-        map.insert("Cornell Box with Glass Sphere (SLOW)".to_string(), cornell_metal_cube_scene as fn(usize, usize) -> Scene);
+        map.insert("Cornell Box with Glass Sphere (SLOW)".to_string(), cornell_glass_sphere_scene as fn(usize, usize) -> Scene);
+        map.insert("Cornell Box with Smoke".to_string(), cornell_smoke_scene as fn(usize, usize) -> Scene);
         map
     };
 }
 
+/// The names of all the built in scenes in alphabetical order.
+pub fn scene_names() -> Vec<&'static str> {
+    let mut names: Vec<&str> = SCENE_MAP.keys().map(|s| s.as_str()).collect();
+    names.sort_unstable();
+    names
+}
+
+/// Builds the built in scene called `name` for an image of the given size.
+pub fn build_scene(name: &str, width: usize, height: usize) -> Result<Scene, UnknownSceneError> {
+    match SCENE_MAP.get(name) {
+        Some(scene) => Ok(scene(width, height)),
+        None => Err(UnknownSceneError { name: name.to_string() }),
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownSceneError {
+    name: String,
+}
+
+impl fmt::Display for UnknownSceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown scene '{}', valid scenes are:", self.name)?;
+        for name in scene_names() {
+            write!(f, "\n  {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for UnknownSceneError {}
+
 pub struct Scene {
     pub objects: HittableList,
     pub camera: Camera,
-    pub background_color: Vec3,
-    pub lights: Arc<dyn Hittable>,
+    pub background: Arc<dyn Background>,
+    /// The objects sampled directly as lights, `None` when the scene is only
+    /// lit by its background
+    pub lights: Option<Arc<dyn Hittable>>,
+    /// The images the scene could not open and drew with a plain color instead
+    pub missing_images: Vec<&'static str>,
+}
+
+impl Scene {
+    /// A scene lit by every emissive object in `objects`, sampled in proportion to their power
+    pub fn new<B: Background + 'static>(objects: HittableList, camera: Camera, background: B) -> Self {
+        let lights = Lights::from_objects(&objects, LightWeighting::Power);
+        Scene {
+            objects,
+            camera,
+            background: Arc::new(background),
+            lights: lights.into_shared(),
+            missing_images: Vec::new(),
+        }
+    }
 }
 
 pub fn book1_objects() -> HittableList {
@@ -127,15 +182,16 @@ pub fn book1_camera(width: usize, height: usize) -> Camera {
 }
 
 pub fn book1_scene(width: usize, height: usize) -> Scene {
-    Scene {
-        objects: book1_objects(),
-        camera: book1_camera(width, height),
-        background_color: Vec3::new(0xdd as f64 / 255.0, 0xec as f64 / 255.0, 0xff as f64 / 255.0),
-        lights: Arc::new(HittableList::new()),
-    }
+    Scene::new(
+        book1_objects(),
+        book1_camera(width, height),
+        SolidBackground::new(0xdd as f64 / 255.0, 0xec as f64 / 255.0, 0xff as f64 / 255.0),
+    )
 }
 
-pub fn book2_objects() -> HittableList {
+/// The objects of the final scene of the second book, the images it could not
+/// open are added to `missing_images`
+pub fn book2_objects(missing_images: &mut Vec<&'static str>) -> HittableList {
     let mut cubes1 = HittableList::new();
 
     let ground = Arc::new(Lambertian::new(SolidTexture::new(0.48, 0.83, 0.53)));
@@ -162,7 +218,8 @@ pub fn book2_objects() -> HittableList {
     objects.add(Arc::new(BVHNode::from_list(&mut cubes1, 0.0, 1.0)));
 
     let light = Arc::new(DiffuseLight::new(SolidTexture::new(7.0, 7.0, 7.0)));
-    objects.add(Arc::new(XZRect::new(light, 123.0, 423.0, 147.0, 412.0, 554.0)));
+    // Lights only shine from their front face, so the ceiling light is flipped to face down
+    objects.add(Arc::new(FlipFace::new(Arc::new(XZRect::new(light, 123.0, 423.0, 147.0, 412.0, 554.0)))));
 
     let center1 = Vec3::new(400.0, 400.0, 200.0);
     let center2 = center1 + Vec3::new(30.0, 0.0, 0.0);
@@ -181,7 +238,13 @@ pub fn book2_objects() -> HittableList {
     let boundry = Arc::new(Sphere::new(&Vec3::new(0.0, 0.0, 0.0), 5000.0, Arc::new(Dielectric::new(1.5))));
     objects.add(Arc::new(ConstantMedium::new(boundry, SolidTexture::new(1.0, 1.0, 1.0), 0.0001)));
 
-    let emat = Arc::new(Lambertian::new(ImageTexture::new("img_files/earthmap.jpg")));
+    let emat: Arc<dyn Material> = match ImageTexture::open(EARTH_MAP) {
+        Ok(texture) => Arc::new(Lambertian::new(texture)),
+        Err(_) => {
+            missing_images.push(EARTH_MAP);
+            Arc::new(Lambertian::new(earth_fallback_texture()))
+        }
+    };
     objects.add(Arc::new(Sphere::new(&Vec3::new(400.0, 200.0, 400.0), 100.0, emat)));
     let pertex = NoiseTexture::new(1.5);
     objects.add(Arc::new(Sphere::new(&Vec3::new(220.0, 280.0, 300.0), 80.0, Arc::new(Lambertian::new(pertex)))));
@@ -204,16 +267,34 @@ pub fn book2_objects() -> HittableList {
     objects
 }
 
-fn book2_scene(width: usize, height: usize) -> Scene {
-    Scene {
-        objects: book2_objects(),
-        camera: book1_camera(width, height),
-        background_color: Vec3::new(0.0, 0.0, 0.0),
-        lights: Arc::new(HittableList::new()),
-    }
+pub fn book2_camera(width: usize, height: usize) -> Camera {
+    let aspect_ratio = width as f64 / height as f64;
+    let lookfrom = Vec3::new(478.0, 278.0, -600.0);
+    let lookat = Vec3::new(278.0, 278.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus = 10.0;
+    let aperture = 0.0;
+    let vfov = 40.0;
+    Camera::new_timed(
+        lookfrom,
+        lookat,
+        vup,
+        vfov,
+        aspect_ratio,
+        aperture,
+        dist_to_focus,
+        0.0,
+        1.0,
+    )
 }
 
-fn two_spheres() -> HittableList {
+pub fn book2_scene(width: usize, height: usize) -> Scene {
+    let mut missing_images = Vec::new();
+    let objects = book2_objects(&mut missing_images);
+    Scene { missing_images, ..Scene::new(objects, book2_camera(width, height), SolidBackground::from(Vec3::zero())) }
+}
+
+pub fn two_spheres() -> HittableList {
     let mut objects = HittableList::new_with_capacity(2);
 
     let checker = Arc::new(Lambertian::new(
@@ -227,7 +308,7 @@ fn two_spheres() -> HittableList {
     objects
 }
 
-fn two_perlin_spheres() -> HittableList {
+pub fn two_perlin_spheres() -> HittableList {
     let mut objects = HittableList::new_with_capacity(2);
 
     let checker = Arc::new(Lambertian::new(NoiseTexture::new(8.0)));
@@ -238,13 +319,23 @@ fn two_perlin_spheres() -> HittableList {
     objects
 }
 
+/// The image used for the earth, which is not included in the repository
+pub const EARTH_MAP: &str = "img_files/earthmap.jpg";
+
+/// Stands in for the earth map when it is missing
+fn earth_fallback_texture() -> SolidTexture {
+    SolidTexture::new(0.2, 0.4, 0.8)
+}
+
 pub fn earth(radius: f64) -> Sphere {
-    let earth_texture = ImageTexture::new("img_files/earthmap.jpg");
-    let earth_surface = Arc::new(DiffuseLight::new(earth_texture));
+    let earth_surface: Arc<dyn Material> = match ImageTexture::open(EARTH_MAP) {
+        Ok(texture) => Arc::new(DiffuseLight::new(texture)),
+        Err(_) => Arc::new(DiffuseLight::new(earth_fallback_texture())),
+    };
     Sphere::new(&Vec3::zero(), radius, earth_surface)
 }
 
-fn simple_light() -> HittableList {
+pub fn simple_light() -> HittableList {
     let mut objects = two_perlin_spheres();
 
     let difflight = Arc::new(DiffuseLight::new(SolidTexture::new(4.0, 4.0, 4.0)));
@@ -310,6 +401,22 @@ pub fn cornell_with_metal_cube() -> HittableList {
     objects
 }
 
+pub fn cornell_with_glass_sphere() -> HittableList {
+    let mut objects = cornell_box();
+
+    let white = Arc::new(Lambertian::new(SolidTexture::new(0.73, 0.73, 0.73)));
+    let glass = Arc::new(Dielectric::new(1.5));
+
+    let cube1 = Cube::new(Vec3::zero(), Vec3::new(165.0, 330.0, 165.0), white);
+    let cube1 = RotateY::new(cube1, 15.0);
+    let cube1 = Arc::new(Translate::new(cube1, Vec3::new(265.0, 0.0, 295.0)));
+    objects.add(cube1);
+
+    objects.add(Arc::new(Sphere::new(&Vec3::new(190.0, 90.0, 190.0), 90.0, glass)));
+
+    objects
+}
+
 pub fn cornell_with_smoke() -> HittableList {
     let mut objects = HittableList::new();
 
@@ -320,7 +427,7 @@ pub fn cornell_with_smoke() -> HittableList {
 
     objects.add(Arc::new(FlipFace::new(Arc::new(YZRect::new(green.clone(), 0.0, 555.0, 0.0, 555.0, 555.0)))));
     objects.add(Arc::new(YZRect::new(red, 0.0, 555.0, 0.0, 555.0, 0.0)));
-    objects.add(Arc::new(XZRect::new(light, 113.0, 443.0, 127.0, 432.0, 554.0)));
+    objects.add(Arc::new(FlipFace::new(Arc::new(XZRect::new(light, 113.0, 443.0, 127.0, 432.0, 554.0)))));
     objects.add(Arc::new(FlipFace::new(Arc::new(XZRect::new(white.clone(), 0.0, 555.0, 0.0, 555.0, 0.0)))));
     objects.add(Arc::new(XZRect::new(white.clone(), 0.0, 555.0, 0.0, 555.0, 555.0)));
     objects.add(Arc::new(FlipFace::new(Arc::new(XYRect::new(white.clone(), 0.0, 555.0, 0.0, 555.0, 555.0)))));
@@ -361,46 +468,36 @@ pub fn cornell_camera(width: usize, height: usize) -> Camera {
 }
 
 pub fn cornell_scene(width: usize, height: usize) -> Scene {
-    let mut lights = HittableList::new();
-    let mat = Arc::new(Lambertian::new(SolidTexture::from(Vec3::zero())));
-    lights.add(Arc::new(XZRect::new(mat, 213.0, 343.0, 227.0, 332.0, 554.0)));
-
-    let world = cornell_box();
-
-    Scene {
-        objects: world,
-        camera: cornell_camera(width, height),
-        background_color: Vec3::zero(),
-        lights: Arc::new(lights),
-    }
+    Scene::new(cornell_box(), cornell_camera(width, height), SolidBackground::from(Vec3::zero()))
 }
 
 pub fn cornell_cubes_scene(width: usize, height: usize) -> Scene {
-    let mut lights = HittableList::new();
-    let mat = Arc::new(Lambertian::new(SolidTexture::from(Vec3::zero())));
-    lights.add(Arc::new(XZRect::new(mat, 213.0, 343.0, 227.0, 332.0, 554.0)));
-
-    let world = cornell_with_cubes();
-
-    Scene {
-        objects: world,
-        camera: cornell_camera(width, height),
-        background_color: Vec3::zero(),
-        lights: Arc::new(lights),
-    }
+    Scene::new(cornell_with_cubes(), cornell_camera(width, height), SolidBackground::from(Vec3::zero()))
 }
 
 pub fn cornell_metal_cube_scene(width: usize, height: usize) -> Scene {
-    let mut lights = HittableList::new();
-    let mat = Arc::new(Lambertian::new(SolidTexture::from(Vec3::zero())));
-    lights.add(Arc::new(XZRect::new(mat, 213.0, 343.0, 227.0, 332.0, 554.0)));
+    Scene::new(cornell_with_metal_cube(), cornell_camera(width, height), SolidBackground::from(Vec3::zero()))
+}
 
-    let world = cornell_with_metal_cube();
+pub fn cornell_glass_sphere_scene(width: usize, height: usize) -> Scene {
+    let world = cornell_with_glass_sphere();
+    let mut lights = Lights::from_objects(&world, LightWeighting::Power);
+
+    // Sampling towards the glass sphere as often as the light helps resolve
+    // the caustic it focuses onto the floor
+    let mat = Arc::new(Lambertian::new(SolidTexture::from(Vec3::zero())));
+    let total = lights.total_weight();
+    lights.add(Arc::new(Sphere::new(&Vec3::new(190.0, 90.0, 190.0), 90.0, mat)), total);
 
     Scene {
         objects: world,
         camera: cornell_camera(width, height),
-        background_color: Vec3::zero(),
-        lights: Arc::new(lights),
+        background: Arc::new(SolidBackground::from(Vec3::zero())),
+        lights: lights.into_shared(),
+        missing_images: Vec::new(),
     }
-}
\ No newline at end of file
+}
+
+pub fn cornell_smoke_scene(width: usize, height: usize) -> Scene {
+    Scene::new(cornell_with_smoke(), cornell_camera(width, height), SolidBackground::from(Vec3::zero()))
+}
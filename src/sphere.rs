@@ -3,6 +3,7 @@ use crate::hittable::{Hittable, HitRecord};
 use crate::ray::Ray;
 use crate::material::Material;
 use crate::aabb::AABB;
+use crate::lights::Emitter;
 
 use std::sync::Arc;
 use std::f64;
@@ -66,27 +67,26 @@ impl Hittable for Sphere {
     fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
         *output_box = AABB::new(
             &(self.center - Vec3::new(self.radius, self.radius, self.radius)),
-            &(self.center - Vec3::new(self.radius, self.radius, self.radius)));
+            &(self.center + Vec3::new(self.radius, self.radius, self.radius)));
         true
     }
 
     fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
-        let mut rec = HitRecord::new();
-        if self.hit(&Ray::new(*o, *v, 0.0), 0.001, f64::INFINITY, &mut rec) {
-            return 0.0;
-        }
-
-        let cos_theta_max = (1.0 - self.radius * self.radius / (self.center - *o).length_squared()).sqrt();
-        let solid_angle = 2.0 * f64::consts::PI * (1.0 - cos_theta_max);
-
-        1.0 / solid_angle
+        cone_pdf(&self.center, self.radius, o, v)
     }
 
     fn random(&self, o: &Vec3) -> Vec3 {
-        let direction = self.center - *o;
-        let distance_squared = direction.length_squared();
-        let uvw = Onb::build_from_w(&direction);
-        uvw.local_vec(&util::random_to_sphere(self.radius, distance_squared))
+        cone_random(&self.center, self.radius, o)
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        if self.material.is_emissive() {
+            emitters.push(Emitter {
+                object: Arc::new(self.clone()),
+                area: 4.0 * f64::consts::PI * self.radius * self.radius,
+                emission: self.material.average_emission(),
+            });
+        }
     }
 }
 
@@ -118,6 +118,13 @@ impl MovingSphere {
         self.center0 + (self.center1 - self.center0)
             .scale((time - self.time0) / (self.time1 - self.time0))
     }
+
+    /// The center and radius of the smallest sphere holding the sphere
+    /// between `time0` and `time1`
+    fn swept_sphere(&self) -> (Vec3, f64) {
+        let center = (self.center0 + self.center1).scale(0.5);
+        (center, self.radius + (self.center1 - self.center0).length() / 2.0)
+    }
 }
 
 impl Hittable for MovingSphere {
@@ -155,11 +162,65 @@ impl Hittable for MovingSphere {
     fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut AABB) -> bool {
         let box0 = AABB::new(
             &(self.center(t0) - Vec3::new(self.radius, self.radius, self.radius)),
-            &(self.center(t0) - Vec3::new(self.radius, self.radius, self.radius)));
+            &(self.center(t0) + Vec3::new(self.radius, self.radius, self.radius)));
         let box1 = AABB::new(
             &(self.center(t1) - Vec3::new(self.radius, self.radius, self.radius)),
-            &(self.center(t1) - Vec3::new(self.radius, self.radius, self.radius)));
+            &(self.center(t1) + Vec3::new(self.radius, self.radius, self.radius)));
         *output_box = box0.surrounding_box(&box1);
         true
     }
+
+    /// Directions are sampled over the cone of the sphere around the whole
+    /// path, as the time of the ray is not known, so every position the
+    /// sphere passes through can be reached
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        let (center, radius) = self.swept_sphere();
+        cone_pdf(&center, radius, o, v)
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        let (center, radius) = self.swept_sphere();
+        cone_random(&center, radius, o)
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        if self.material.is_emissive() {
+            emitters.push(Emitter {
+                object: Arc::new(self.clone()),
+                area: 4.0 * f64::consts::PI * self.radius * self.radius,
+                emission: self.material.average_emission(),
+            });
+        }
+    }
+}
+
+/// The density of directions from `o` sampled uniformly over the cone a
+/// sphere covers, or over all directions from inside the sphere where every
+/// direction hits it
+fn cone_pdf(center: &Vec3, radius: f64, o: &Vec3, v: &Vec3) -> f64 {
+    let direction = *center - *o;
+    let distance_squared = direction.length_squared();
+    if distance_squared <= radius * radius {
+        return 1.0 / (4.0 * f64::consts::PI);
+    }
+
+    let cos_theta_max = (1.0 - radius * radius / distance_squared).sqrt();
+    if v.unit_vector().dot(&direction.unit_vector()) < cos_theta_max {
+        return 0.0;
+    }
+
+    let solid_angle = 2.0 * f64::consts::PI * (1.0 - cos_theta_max);
+    1.0 / solid_angle
+}
+
+/// A direction from `o` with the density of `cone_pdf`
+fn cone_random(center: &Vec3, radius: f64, o: &Vec3) -> Vec3 {
+    let direction = *center - *o;
+    let distance_squared = direction.length_squared();
+    if distance_squared <= radius * radius {
+        return Vec3::random_unit_vector();
+    }
+
+    let uvw = Onb::build_from_w(&direction);
+    uvw.local_vec(&util::random_to_sphere(radius, distance_squared))
 }
@@ -0,0 +1,108 @@
+//! Reader for the triangles of STL files, in ASCII or binary.
+//!
+//! STL stores every triangle with its own copy of its vertices, so identical
+//! vertices are merged into one. Each triangle's winding is made to agree
+//! with its stored normal, as not every exporter keeps the two consistent.
+//! https://en.wikipedia.org/wiki/STL_(file_format)
+
+use crate::mesh::MeshError;
+use crate::vec3::Vec3;
+
+use std::collections::HashMap;
+
+/// The geometry read from an STL file
+pub(crate) struct StlData {
+    pub positions: Vec<Vec3>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+/// Size of the binary header, including the triangle count
+const HEADER_SIZE: usize = 84;
+
+/// Size of a binary triangle, its normal, vertices and attribute byte count
+const TRIANGLE_SIZE: usize = 50;
+
+/// Reads the triangles of the STL file in `bytes`
+pub(crate) fn parse(bytes: &[u8]) -> Result<StlData, MeshError> {
+    // Some binary files also start with "solid", so the size decides the format
+    let binary_count = bytes.get(80..HEADER_SIZE)
+        .map(|count| u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize)
+        .filter(|&count| bytes.len() == HEADER_SIZE + count * TRIANGLE_SIZE);
+
+    let mut builder = Builder { positions: Vec::new(), triangles: Vec::new(), indices: HashMap::new() };
+
+    match binary_count {
+        Some(count) => {
+            let float = |b: &[u8], i: usize| f32::from_le_bytes([b[4 * i], b[4 * i + 1], b[4 * i + 2], b[4 * i + 3]]);
+            builder.triangles.reserve(count);
+            for triangle in bytes[HEADER_SIZE..].chunks_exact(TRIANGLE_SIZE) {
+                let v: Vec<[f32; 3]> = (0..4).map(|i| [float(triangle, 3 * i), float(triangle, 3 * i + 1), float(triangle, 3 * i + 2)]).collect();
+                builder.add(v[0], &v[1..]);
+            }
+        }
+        None if bytes.starts_with(b"solid") => {
+            let text = std::str::from_utf8(bytes)
+                .map_err(|_| MeshError::Parse("ASCII STL file is not valid text".to_string()))?;
+            let mut tokens = text.split_ascii_whitespace();
+            let vector = |tokens: &mut std::str::SplitAsciiWhitespace| -> Result<[f32; 3], MeshError> {
+                let mut v = [0.0; 3];
+                for c in v.iter_mut() {
+                    let token = tokens.next().ok_or_else(|| MeshError::Parse("unexpected end of STL file".to_string()))?;
+                    *c = token.parse().map_err(|_| MeshError::Parse(format!("invalid STL number '{}'", token)))?;
+                }
+                Ok(v)
+            };
+
+            let mut normal = [0.0; 3];
+            let mut facet = Vec::new();
+            while let Some(token) = tokens.next() {
+                match token {
+                    "normal" => normal = vector(&mut tokens)?,
+                    "vertex" => facet.push(vector(&mut tokens)?),
+                    "endfacet" => {
+                        builder.add(normal, &facet);
+                        facet.clear();
+                    }
+                    _ => (),
+                }
+            }
+        }
+        None => return Err(MeshError::Parse("not an STL file".to_string())),
+    }
+
+    Ok(StlData { positions: builder.positions, triangles: builder.triangles })
+}
+
+struct Builder {
+    positions: Vec<Vec3>,
+    triangles: Vec<[u32; 3]>,
+    /// Index of each distinct vertex by the bits of its coordinates
+    indices: HashMap<[u32; 3], u32>,
+}
+
+impl Builder {
+    /// Adds a facet, split into a fan when it has more than three vertices
+    fn add(&mut self, normal: [f32; 3], facet: &[[f32; 3]]) {
+        let normal = Vec3::new(normal[0] as f64, normal[1] as f64, normal[2] as f64);
+        let indices: Vec<u32> = facet.iter().map(|v| self.index(v)).collect();
+
+        for i in 2..indices.len() {
+            let (a, b, c) = (indices[0], indices[i - 1], indices[i]);
+            let (pa, pb, pc) = (self.positions[a as usize], self.positions[b as usize], self.positions[c as usize]);
+            if (pb - pa).cross(&(pc - pa)).dot(&normal) < 0.0 {
+                self.triangles.push([a, c, b]);
+            } else {
+                self.triangles.push([a, b, c]);
+            }
+        }
+    }
+
+    fn index(&mut self, v: &[f32; 3]) -> u32 {
+        let positions = &mut self.positions;
+        // Adding zero turns -0 into 0 so both merge
+        *self.indices.entry(v.map(|c| (c + 0.0).to_bits())).or_insert_with(|| {
+            positions.push(Vec3::new(v[0] as f64, v[1] as f64, v[2] as f64));
+            positions.len() as u32 - 1
+        })
+    }
+}
@@ -60,6 +60,28 @@ impl<T, U> Texture for CheckerTexture<T, U>
     }
 }
 
+/// Another texture multiplied by a color
+#[derive(Clone)]
+pub struct TintedTexture<T>
+    where T: Texture {
+    texture: T,
+    tint: Vec3,
+}
+
+impl<T> TintedTexture<T>
+    where T: Texture {
+    pub fn new(texture: T, tint: Vec3) -> Self {
+        TintedTexture { texture, tint }
+    }
+}
+
+impl<T> Texture for TintedTexture<T>
+    where T: Texture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
+        self.texture.value(u, v, p) * self.tint
+    }
+}
+
 #[derive(Clone)]
 pub struct NoiseTexture {
     noise: Perlin,
@@ -75,7 +97,7 @@ impl NoiseTexture {
 impl Texture for NoiseTexture {
     fn value(&self, _u: f64, _v: f64, p: &Vec3) -> Vec3 {
         Vec3::new(1.0, 1.0, 1.0)
-            .scale(0.5 * (1.0 + (self.scale * p.z() + 10.0 * self.noise.turb(&p, 7)).sin()))
+            .scale(0.5 * (1.0 + (self.scale * p.z() + 10.0 * self.noise.turb(p, 7)).sin()))
     }
 }
 
@@ -88,11 +110,18 @@ pub struct ImageTexture {
 
 impl ImageTexture {
     pub fn new(path: &str) -> Self {
-        let img = image::open(path).unwrap();
+        ImageTexture::open(path).unwrap()
+    }
+
+    pub fn open(path: &str) -> image::ImageResult<Self> {
+        Ok(ImageTexture::from_image(image::open(path)?))
+    }
+
+    pub fn from_image(image: image::DynamicImage) -> Self {
         ImageTexture {
-            width: img.dimensions().0,
-            height: img.dimensions().1,
-            image: img,
+            width: image.dimensions().0,
+            height: image.dimensions().1,
+            image,
         }
     }
 }
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A rectangle of pixels rendered as one unit of work, `x1` and `y1` are exclusive
+/// and rows are counted from the top of the image
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tile {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Tile {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    pub fn pixels(&self) -> usize {
+        self.width() * self.height()
+    }
+}
+
+/// The order tiles are scheduled in
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum TileOrder {
+    /// Left to right, top to bottom
+    Scanline,
+    /// Outwards from the center of the image, where the subject usually is
+    #[default]
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles next to each other
+    /// https://en.wikipedia.org/wiki/Hilbert_curve
+    Hilbert,
+}
+
+impl TileOrder {
+    pub const NAMES: [&'static str; 3] = ["scanline", "spiral", "hilbert"];
+}
+
+impl FromStr for TileOrder {
+    type Err = UnknownTileOrderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(UnknownTileOrderError { name: s.to_string() }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownTileOrderError {
+    name: String,
+}
+
+impl fmt::Display for UnknownTileOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown tile order '{}', valid orders are: {}", self.name, TileOrder::NAMES.join(", "))
+    }
+}
+
+impl std::error::Error for UnknownTileOrderError {}
+
+/// Splits an image into square tiles of `tile_size` pixels, the tiles on the
+/// right and bottom edges are smaller when the size does not divide evenly
+pub fn tiles(width: usize, height: usize, tile_size: usize, order: TileOrder) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let tiles_x = width.div_ceil(tile_size);
+    let tiles_y = height.div_ceil(tile_size);
+
+    let mut coords: Vec<(usize, usize)> = (0..tiles_y)
+        .flat_map(|ty| (0..tiles_x).map(move |tx| (tx, ty)))
+        .collect();
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            let cx = (tiles_x as f64 - 1.0) / 2.0;
+            let cy = (tiles_y as f64 - 1.0) / 2.0;
+            coords.sort_by(|a, b| {
+                spiral_key(a.0, a.1, cx, cy).partial_cmp(&spiral_key(b.0, b.1, cx, cy)).unwrap()
+            });
+        }
+        TileOrder::Hilbert => {
+            let n = tiles_x.max(tiles_y).next_power_of_two();
+            coords.sort_by_key(|&(tx, ty)| hilbert_index(n, tx, ty));
+        }
+    }
+
+    coords.into_iter()
+        .map(|(tx, ty)| Tile {
+            x0: tx * tile_size,
+            y0: ty * tile_size,
+            x1: ((tx + 1) * tile_size).min(width),
+            y1: ((ty + 1) * tile_size).min(height),
+        })
+        .collect()
+}
+
+/// Orders tiles by the square ring around the center they lie on, then by angle
+fn spiral_key(tx: usize, ty: usize, cx: f64, cy: f64) -> (f64, f64) {
+    let dx = tx as f64 - cx;
+    let dy = ty as f64 - cy;
+    (dx.abs().max(dy.abs()).round(), dy.atan2(dx))
+}
+
+/// The distance along a Hilbert curve filling an `n` by `n` grid, `n` must be a power of two
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+
+    while s > 0 {
+        let rx = (x & s > 0) as usize;
+        let ry = (y & s > 0) as usize;
+        d += s * s * ((3 * rx) ^ ry);
+
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        s /= 2;
+    }
+
+    d
+}
@@ -0,0 +1,122 @@
+use crate::util::clamp;
+use crate::vec3::Vec3;
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Operators that compress linear radiance into the displayable range
+/// https://64.github.io/tonemapping/
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum ToneMap {
+    /// Clips every channel above 1
+    #[default]
+    Clamp,
+    /// Reinhard's operator applied to the luminance
+    Reinhard,
+    /// Reinhard's operator where a luminance of `white` maps to 1
+    ExtendedReinhard(f64),
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl ToneMap {
+    pub const NAMES: [&'static str; 4] = ["clamp", "reinhard", "reinhard-extended", "aces"];
+
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        match *self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMap::ExtendedReinhard(white) =>
+                scale_luminance(color, |l| l * (1.0 + l / (white * white)) / (1.0 + l)),
+            ToneMap::Aces => Vec3::new(aces(color.x()), aces(color.y()), aces(color.z())),
+        }
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = UnknownToneMapError;
+
+    /// Parses one of `ToneMap::NAMES`, the extended Reinhard operator defaults to a white point of 4
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "reinhard-extended" => Ok(ToneMap::ExtendedReinhard(4.0)),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(UnknownToneMapError { name: s.to_string() }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownToneMapError {
+    name: String,
+}
+
+impl fmt::Display for UnknownToneMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown tone map '{}', valid tone maps are: {}", self.name, ToneMap::NAMES.join(", "))
+    }
+}
+
+impl std::error::Error for UnknownToneMapError {}
+
+/// Converts linear radiance to 8 bit sRGB colors
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ToneMapper {
+    pub tone_map: ToneMap,
+    /// Exposure adjustment in stops, each stop doubles the brightness
+    pub exposure: f64,
+}
+
+impl ToneMapper {
+    pub fn new(tone_map: ToneMap, exposure: f64) -> Self {
+        ToneMapper { tone_map, exposure }
+    }
+
+    pub fn map(&self, color: &Vec3) -> (u8, u8, u8) {
+        let mapped = self.tone_map.apply(color.scale(2.0_f64.powf(self.exposure)));
+
+        (quantize(srgb_encode(mapped.x())),
+         quantize(srgb_encode(mapped.y())),
+         quantize(srgb_encode(mapped.z())))
+    }
+}
+
+pub(crate) fn luminance(color: &Vec3) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+fn scale_luminance<F: Fn(f64) -> f64>(color: Vec3, f: F) -> Vec3 {
+    let l = luminance(&color);
+    if l <= 0.0 {
+        return color;
+    }
+    color.scale(f(l) / l)
+}
+
+fn aces(x: f64) -> f64 {
+    const A: f64 = 2.51;
+    const B: f64 = 0.03;
+    const C: f64 = 2.43;
+    const D: f64 = 0.59;
+    const E: f64 = 0.14;
+    (x * (A * x + B)) / (x * (C * x + D) + E)
+}
+
+/// The sRGB transfer function for a linear value in [0, 1]
+/// https://en.wikipedia.org/wiki/SRGB#Transfer_function_(%22gamma%22)
+pub fn srgb_encode(linear: f64) -> f64 {
+    if linear <= 0.003_130_8 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn quantize(x: f64) -> u8 {
+    if x.is_nan() {
+        return 0;
+    }
+    (256.0 * clamp(x, 0.0, 0.999)) as u8
+}
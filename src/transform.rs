@@ -0,0 +1,207 @@
+use crate::hittable::{Hittable, HitRecord};
+use crate::aabb::AABB;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use crate::lights::Emitter;
+
+use nalgebra::{Matrix4, Point3, Unit, Vector3, U3};
+use std::fmt;
+use std::sync::Arc;
+
+/// A transform that flattens space, such as a scale by zero, which rays
+/// cannot be moved back through
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SingularTransformError;
+
+impl fmt::Display for SingularTransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "transform matrix is not invertible")
+    }
+}
+
+impl std::error::Error for SingularTransformError {}
+
+/// An object placed in the world by an affine transform.
+///
+/// Rays are moved into the object's space by the inverse of the matrix and
+/// hits are moved back out, with normals transformed by the inverse transpose
+/// so they stay perpendicular under scaling and shearing. The object is
+/// shared through an `Arc`, so one mesh can be placed many times while its
+/// triangles are only stored once.
+///
+/// The builder methods apply after the existing transform, so
+/// `Transform::new(object).scale(s)?.translate(t)?` scales then translates.
+pub struct Transform {
+    object: Arc<dyn Hittable>,
+    matrix: Matrix4<f64>,
+    inverse: Matrix4<f64>,
+    /// The inverse transpose, which transforms normals
+    normal_matrix: Matrix4<f64>,
+    /// The object's own bounding box, which the world box is made from
+    object_box: Option<AABB>,
+    bbox: Option<AABB>,
+}
+
+impl Transform {
+    /// Places `object` without moving it
+    pub fn new(object: Arc<dyn Hittable>) -> Self {
+        Transform::with_inverse(object, Matrix4::identity(), Matrix4::identity())
+    }
+
+    /// Places `object` by `matrix`, which must be invertible
+    pub fn from_matrix(object: Arc<dyn Hittable>, matrix: Matrix4<f64>) -> Result<Self, SingularTransformError> {
+        Transform::new(object).then(matrix)
+    }
+
+    fn with_inverse(object: Arc<dyn Hittable>, matrix: Matrix4<f64>, inverse: Matrix4<f64>) -> Self {
+        let mut object_box = AABB::new_max();
+        let object_box = if object.bounding_box(0.0, 1.0, &mut object_box) { Some(object_box) } else { None };
+
+        Transform {
+            object,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            object_box,
+            bbox: object_box.map(|aabb_box| transform_box(&matrix, &aabb_box)),
+        }
+    }
+
+    pub fn translate(self, offset: Vec3) -> Result<Self, SingularTransformError> {
+        self.then(Matrix4::new_translation(&to_vector(&offset)))
+    }
+
+    /// Rotates by `angle` degrees counterclockwise about `axis` through the origin
+    pub fn rotate(self, axis: Vec3, angle: f64) -> Result<Self, SingularTransformError> {
+        let axis = Unit::new_normalize(to_vector(&axis));
+        self.then(Matrix4::from_axis_angle(&axis, angle.to_radians()))
+    }
+
+    /// Scales by a separate factor along each axis
+    pub fn scale(self, factors: Vec3) -> Result<Self, SingularTransformError> {
+        self.then(Matrix4::new_nonuniform_scaling(&to_vector(&factors)))
+    }
+
+    /// Turns the object's +z axis toward `to` with its +y axis toward `up`,
+    /// and moves its origin to `from`
+    pub fn look_at(self, from: Vec3, to: Vec3, up: Vec3) -> Result<Self, SingularTransformError> {
+        let from = Point3::from(to_vector(&from));
+        let to = Point3::from(to_vector(&to));
+        self.then(Matrix4::face_towards(&from, &to, &to_vector(&up)))
+    }
+
+    /// Applies `matrix` after the existing transform
+    pub fn then(mut self, matrix: Matrix4<f64>) -> Result<Self, SingularTransformError> {
+        self.apply(matrix)?;
+        Ok(self)
+    }
+
+    /// The matrix from the object's space to the world
+    pub fn matrix(&self) -> &Matrix4<f64> {
+        &self.matrix
+    }
+
+    /// Leaves the transform as it was when the combined matrix cannot be
+    /// inverted, including when it holds NaNs from a degenerate rotation
+    fn apply(&mut self, matrix: Matrix4<f64>) -> Result<(), SingularTransformError> {
+        let combined = matrix * self.matrix;
+        let inverse = combined.try_inverse()
+            .filter(|inverse| combined.iter().chain(inverse.iter()).all(|x| x.is_finite()))
+            .ok_or(SingularTransformError)?;
+
+        self.matrix = combined;
+        self.inverse = inverse;
+        self.normal_matrix = inverse.transpose();
+        self.bbox = self.object_box.map(|aabb_box| transform_box(&self.matrix, &aabb_box));
+        Ok(())
+    }
+
+    fn to_object_point(&self, p: &Vec3) -> Vec3 {
+        to_vec3(&self.inverse.transform_point(&Point3::from(to_vector(p))).coords)
+    }
+
+    fn to_object_vector(&self, v: &Vec3) -> Vec3 {
+        to_vec3(&self.inverse.transform_vector(&to_vector(v)))
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // The direction is not normalized so distances along the ray are the same in both spaces
+        let object_r = Ray::new(self.to_object_point(&r.origin()), self.to_object_vector(&r.direction()), r.time());
+
+        if !self.object.hit(&object_r, t_min, t_max, rec) {
+            return false;
+        }
+
+        rec.p = to_vec3(&self.matrix.transform_point(&Point3::from(to_vector(&rec.p))).coords);
+        // Transforming by the inverse transpose keeps the normal's dot product
+        // with the ray's direction, so which face was hit stays the same
+        let normal = self.normal_matrix.transform_vector(&to_vector(&rec.normal));
+        rec.normal = to_vec3(&normal).unit_vector();
+
+        true
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        match self.bbox {
+            Some(aabb_box) => {
+                *output_box = aabb_box;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The object's density in its own space times how much the transform
+    /// stretches solid angles, `|det M⁻¹| / |M⁻¹ ω|³` for a unit direction ω
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        let object_v = self.to_object_vector(&v.unit_vector());
+        let stretch = self.inverse.fixed_slice::<U3, U3>(0, 0).determinant().abs()
+            / object_v.length().powi(3);
+
+        self.object.pdf_value(&self.to_object_point(o), &object_v) * stretch
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        let v = self.object.random(&self.to_object_point(o));
+        to_vec3(&self.matrix.transform_vector(&to_vector(&v)))
+    }
+
+    /// Areas are scaled as if the transform stretched every direction evenly,
+    /// which is exact for rotations and uniform scales
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        let first = emitters.len();
+        self.object.emitters(emitters);
+
+        let area_scale = self.matrix.fixed_slice::<U3, U3>(0, 0).determinant().abs().powf(2.0 / 3.0);
+        for emitter in &mut emitters[first..] {
+            emitter.object = Arc::new(Transform::with_inverse(emitter.object.clone(), self.matrix, self.inverse));
+            emitter.area *= area_scale;
+        }
+    }
+}
+
+/// The box around the eight transformed corners of `aabb_box`
+fn transform_box(matrix: &Matrix4<f64>, aabb_box: &AABB) -> AABB {
+    let corners = [aabb_box.min(), aabb_box.max()];
+    if corners.iter().any(|c| !(c.x().is_finite() && c.y().is_finite() && c.z().is_finite())) {
+        let infinity = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        return AABB::new(&-infinity, &infinity);
+    }
+
+    let mut output_box = AABB::new_max();
+    for i in 0..8 {
+        let corner = Point3::new(corners[i & 1].x(), corners[(i >> 1) & 1].y(), corners[i >> 2].z());
+        output_box.add_point(&to_vec3(&matrix.transform_point(&corner).coords));
+    }
+    output_box
+}
+
+fn to_vector(v: &Vec3) -> Vector3<f64> {
+    Vector3::new(v.x(), v.y(), v.z())
+}
+
+fn to_vec3(v: &Vector3<f64>) -> Vec3 {
+    Vec3::new(v.x, v.y, v.z)
+}
@@ -3,6 +3,8 @@ use crate::hittable::{Hittable, HitRecord};
 use crate::ray::Ray;
 use crate::material::Material;
 use crate::aabb::AABB;
+use crate::lights::Emitter;
+use crate::util;
 
 use std::sync::Arc;
 
@@ -20,6 +22,8 @@ pub struct Triangle {
     e1: Vec3,
     e2: Vec3,
     norm: Norm,
+    /// Texture coordinates of each vertex, barycentric coordinates are used without them
+    uvs: Option<[(f64, f64); 3]>,
     material: Arc<dyn Material>,
 }
 
@@ -35,6 +39,7 @@ impl Triangle {
             e1,
             e2,
             norm: Norm::Flat(norm),
+            uvs: None,
             material,
         }
     }
@@ -49,9 +54,19 @@ impl Triangle {
             e1,
             e2,
             norm: Norm::Smooth(n1, n2, n3),
+            uvs: None,
             material,
         }
     }
+
+    pub fn area(&self) -> f64 {
+        0.5 * self.e1.cross(&self.e2).length()
+    }
+
+    /// Sets the texture coordinates of `p1`, `p2` and `p3`
+    pub fn set_uvs(&mut self, uv1: (f64, f64), uv2: (f64, f64), uv3: (f64, f64)) {
+        self.uvs = Some([uv1, uv2, uv3]);
+    }
 }
 
 impl Hittable for Triangle {
@@ -67,7 +82,7 @@ impl Hittable for Triangle {
         let f = 1.0 / a;
         let s = r.origin() - self.p1;
         let u = f * s.dot(&h);
-        if u < 0.0 || u > 1.0 {
+        if !(0.0..=1.0).contains(&u) {
             return false;
         }
         let q = s.cross(&self.e1);
@@ -79,14 +94,26 @@ impl Hittable for Triangle {
         let pos = r.at(t);
 
         if t > t_min && t < t_max {
-            match self.norm {
-                Norm::Smooth(n1, n2, n3) => {
-                    rec.normal = n2.scale(u) + n3.scale(v) + n1.scale(1.0 - u - v);
+            // Which side was hit comes from the winding order, even with interpolated normals
+            rec.front_face = r.direction().dot(&self.e1.cross(&self.e2)) < 0.0;
+            let normal = match self.norm {
+                Norm::Smooth(n1, n2, n3) => (n2.scale(u) + n3.scale(v) + n1.scale(1.0 - u - v)).unit_vector(),
+                Norm::Flat(norm) => norm,
+            };
+            rec.normal = if rec.front_face { normal } else { -normal };
+
+            match self.uvs {
+                Some([uv1, uv2, uv3]) => {
+                    let w = 1.0 - u - v;
+                    rec.u = w * uv1.0 + u * uv2.0 + v * uv3.0;
+                    rec.v = w * uv1.1 + u * uv2.1 + v * uv3.1;
                 }
-                Norm::Flat(norm) => {
-                    rec.normal = norm;
+                None => {
+                    rec.u = u;
+                    rec.v = v;
                 }
             }
+
             rec.t = t;
             rec.p = pos;
             rec.mat = self.material.clone();
@@ -105,9 +132,40 @@ impl Hittable for Triangle {
         let maxy = self.p1.y().max(self.p2.y().max(self.p3.y()));
         let maxz = self.p1.z().max(self.p2.z().max(self.p3.z()));
 
-        output_box.min = Vec3::new(minx, miny, minz);
-        output_box.max = Vec3::new(maxx, maxy, maxz);
+        // Pad the box so triangles lying in an axis aligned plane do not have a box with no volume
+        const PAD: f64 = 0.0001;
+        output_box.min = Vec3::new(minx - PAD, miny - PAD, minz - PAD);
+        output_box.max = Vec3::new(maxx + PAD, maxy + PAD, maxz + PAD);
 
         true
     }
+    /// The solid angle density of `random`, which samples points uniformly by area
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(*o, *v, 0.0), 0.001, f64::INFINITY, &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = rec.t * rec.t * v.length_squared();
+        let cosine = (v.dot(&self.e1.cross(&self.e2).unit_vector()) / v.length()).abs();
+
+        distance_squared / (cosine * self.area())
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        let r1 = util::random_double().sqrt();
+        let r2 = util::random_double();
+        let point = self.p1 + self.e1.scale(r1 * (1.0 - r2)) + self.e2.scale(r1 * r2);
+        point - *o
+    }
+
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        if self.material.is_emissive() {
+            emitters.push(Emitter {
+                object: Arc::new(self.clone()),
+                area: self.area(),
+                emission: self.material.average_emission(),
+            });
+        }
+    }
 }
@@ -0,0 +1,311 @@
+use crate::hittable::{Hittable, HitRecord};
+use crate::material::Material;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::aabb::AABB;
+use crate::bvh::{BVHSplit, BVHStats, Primitive};
+use crate::flat_bvh::FlatNodes;
+use crate::util;
+use crate::lights::Emitter;
+
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::Arc;
+
+/// A triangle mesh whose triangles index into shared vertex buffers.
+///
+/// Compared to a BVH of `Triangle`s each vertex is stored once, a triangle
+/// only costs its three indices and a material index, and intersection goes
+/// through a flat BVH over the triangles without any dynamic dispatch.
+pub struct TriangleMesh {
+    positions: Vec<Vec3>,
+    /// Per vertex normals, triangles are flat shaded when empty
+    normals: Vec<Vec3>,
+    /// Per vertex texture coordinates, barycentric coordinates are used when empty
+    uvs: Vec<(f64, f64)>,
+    triangles: Vec<[u32; 3]>,
+    materials: Vec<Arc<dyn Material>>,
+    /// Index into `materials` for each triangle, all triangles use the first material when empty
+    material_ids: Vec<u32>,
+    /// Running total of the triangles' areas, used to sample points uniformly over the surface
+    area_cdf: Vec<f64>,
+    nodes: FlatNodes,
+}
+
+/// The buffers a `TriangleMesh` is made from
+#[derive(Clone, Default)]
+pub struct TriangleMeshData {
+    pub positions: Vec<Vec3>,
+    /// Either empty or one normal per position
+    pub normals: Vec<Vec3>,
+    /// Either empty or one texture coordinate per position
+    pub uvs: Vec<(f64, f64)>,
+    pub triangles: Vec<[u32; 3]>,
+    pub materials: Vec<Arc<dyn Material>>,
+    /// Either empty or one material index per triangle
+    pub material_ids: Vec<u32>,
+}
+
+impl TriangleMesh {
+    pub fn new(data: TriangleMeshData, split: BVHSplit) -> Self {
+        let TriangleMeshData { positions, normals, uvs, triangles, materials, material_ids } = data;
+        assert!(normals.is_empty() || normals.len() == positions.len(), "need one normal per position");
+        assert!(uvs.is_empty() || uvs.len() == positions.len(), "need one texture coordinate per position");
+        assert!(material_ids.is_empty() || material_ids.len() == triangles.len(), "need one material per triangle");
+        assert!(!materials.is_empty(), "need at least one material");
+
+        let mut primitives: Vec<Primitive> = triangles.iter()
+            .enumerate()
+            .map(|(i, triangle)| {
+                let mut aabb_box = AABB::new_max();
+                for &vertex in triangle {
+                    aabb_box.add_point(&positions[vertex as usize]);
+                }
+                // Pad the box so triangles lying in an axis aligned plane do not have a box with no volume
+                let pad = Vec3::new(0.0001, 0.0001, 0.0001);
+                Primitive::new(i, AABB::new(&(aabb_box.min() - pad), &(aabb_box.max() + pad)))
+            })
+            .collect();
+
+        let nodes = FlatNodes::build(&mut primitives, split);
+
+        // Store the triangles in the order of the BVH's leaves
+        let ordered_triangles: Vec<[u32; 3]> = primitives.iter().map(|p| triangles[p.index]).collect();
+        let ordered_material_ids = if material_ids.is_empty() {
+            material_ids
+        } else {
+            primitives.iter().map(|p| material_ids[p.index]).collect()
+        };
+
+        let area_cdf = ordered_triangles.iter()
+            .scan(0.0, |total, &[a, b, c]| {
+                let (a, b, c) = (positions[a as usize], positions[b as usize], positions[c as usize]);
+                *total += 0.5 * (b - a).cross(&(c - a)).length();
+                Some(*total)
+            })
+            .collect();
+
+        TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            triangles: ordered_triangles,
+            materials,
+            material_ids: ordered_material_ids,
+            area_cdf,
+            nodes,
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn bvh_stats(&self) -> BVHStats {
+        self.nodes.stats()
+    }
+
+    /// The memory used by the buffers and the BVH in bytes, not counting the materials
+    pub fn memory_usage(&self) -> usize {
+        self.positions.capacity() * size_of::<Vec3>()
+            + self.normals.capacity() * size_of::<Vec3>()
+            + self.uvs.capacity() * size_of::<(f64, f64)>()
+            + self.triangles.capacity() * size_of::<[u32; 3]>()
+            + self.materials.capacity() * size_of::<Arc<dyn Material>>()
+            + self.material_ids.capacity() * size_of::<u32>()
+            + self.area_cdf.capacity() * size_of::<f64>()
+            + self.nodes.memory_usage()
+    }
+
+    /// The total surface area of the triangles
+    pub fn area(&self) -> f64 {
+        self.area_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    /// A mesh of only the triangles using the material at index `material_id`,
+    /// with just the vertices they use
+    fn submesh(&self, material_id: u32) -> TriangleMesh {
+        let mut data = TriangleMeshData {
+            materials: vec![self.materials[material_id as usize].clone()],
+            ..TriangleMeshData::default()
+        };
+        let mut indices = HashMap::new();
+
+        for (i, triangle) in self.triangles.iter().enumerate() {
+            if self.material_ids.get(i).copied().unwrap_or(0) != material_id {
+                continue;
+            }
+            let positions = &mut data.positions;
+            data.triangles.push(triangle.map(|vertex| *indices.entry(vertex).or_insert_with(|| {
+                positions.push(self.positions[vertex as usize]);
+                positions.len() as u32 - 1
+            })));
+        }
+
+        TriangleMesh::new(data, BVHSplit::Sah)
+    }
+
+    fn vertices(&self, triangle: usize) -> (usize, usize, usize) {
+        let [a, b, c] = self.triangles[triangle];
+        (a as usize, b as usize, c as usize)
+    }
+
+    /// The unnormalized normal of a triangle's plane, facing the side its vertices wind counterclockwise around
+    fn geometric_normal(&self, triangle: usize) -> Vec3 {
+        let (a, b, c) = self.vertices(triangle);
+        (self.positions[b] - self.positions[a]).cross(&(self.positions[c] - self.positions[a]))
+    }
+
+    /// The closest triangle `r` hits, with the distance and barycentric coordinates of the hit
+    fn closest_hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(usize, f64, f64, f64)> {
+        let mut closest = None;
+        self.nodes.hit(r, t_min, t_max, |i, closest_t| {
+            let (t, u, v) = self.intersect(i, r, t_min, closest_t)?;
+            closest = Some((i, t, u, v));
+            Some(t)
+        });
+        closest
+    }
+
+    /// Möller–Trumbore intersection returning the distance and barycentric coordinates of the hit
+    /// https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm
+    fn intersect(&self, triangle: usize, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        const EPSILON: f64 = 0.000_000_1;
+
+        let (a, b, c) = self.vertices(triangle);
+        let p1 = self.positions[a];
+        let e1 = self.positions[b] - p1;
+        let e2 = self.positions[c] - p1;
+
+        let h = r.direction().cross(&e2);
+        let det = e1.dot(&h);
+        if det > -EPSILON && det < EPSILON {
+            return None;
+        }
+        let f = 1.0 / det;
+        let s = r.origin() - p1;
+        let u = f * s.dot(&h);
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(&e1);
+        let v = f * r.direction().dot(&q);
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = f * e2.dot(&q);
+
+        if t > t_min && t < t_max {
+            Some((t, u, v))
+        } else {
+            None
+        }
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (triangle, t, u, v) = match self.closest_hit(r, t_min, t_max) {
+            Some(closest) => closest,
+            None => return false,
+        };
+
+        let (a, b, c) = self.vertices(triangle);
+        let w = 1.0 - u - v;
+
+        // Which side was hit comes from the winding order, even with interpolated normals
+        let geometric_normal = self.geometric_normal(triangle);
+        rec.front_face = r.direction().dot(&geometric_normal) < 0.0;
+        let normal = if self.normals.is_empty() {
+            geometric_normal.unit_vector()
+        } else {
+            (self.normals[a].scale(w) + self.normals[b].scale(u) + self.normals[c].scale(v)).unit_vector()
+        };
+        rec.normal = if rec.front_face { normal } else { -normal };
+
+        if self.uvs.is_empty() {
+            rec.u = u;
+            rec.v = v;
+        } else {
+            let (uv1, uv2, uv3) = (self.uvs[a], self.uvs[b], self.uvs[c]);
+            rec.u = w * uv1.0 + u * uv2.0 + v * uv3.0;
+            rec.v = w * uv1.1 + u * uv2.1 + v * uv3.1;
+        }
+
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.mat = match self.material_ids.get(triangle) {
+            Some(&id) => self.materials[id as usize].clone(),
+            None => self.materials[0].clone(),
+        };
+
+        true
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        match self.nodes.bounding_box() {
+            Some(aabb_box) => {
+                *output_box = aabb_box;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The solid angle density of `random`, which samples points uniformly by
+    /// area, so the cosine is taken with the flat triangle and not its
+    /// interpolated normal. Every triangle along `v` adds its density, as the
+    /// hidden ones are sampled too.
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        let r = Ray::new(*o, *v, 0.0);
+        let mut density = 0.0;
+        self.nodes.hit(&r, 0.001, f64::INFINITY, |triangle, _| {
+            if let Some((t, _, _)) = self.intersect(triangle, &r, 0.001, f64::INFINITY) {
+                let distance_squared = t * t * v.length_squared();
+                let cosine = (v.dot(&self.geometric_normal(triangle).unit_vector()) / v.length()).abs();
+                density += distance_squared / cosine;
+            }
+            // Never narrowing the search keeps every triangle along the ray
+            None
+        });
+
+        density / self.area()
+    }
+
+    fn random(&self, o: &Vec3) -> Vec3 {
+        if self.triangles.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+
+        // Pick a triangle with probability proportional to its area, then a uniform point on it
+        let target = util::random_double() * self.area();
+        let triangle = self.area_cdf.partition_point(|&total| total < target).min(self.triangles.len() - 1);
+        let (a, b, c) = self.vertices(triangle);
+
+        let r1 = util::random_double().sqrt();
+        let r2 = util::random_double();
+        let point = self.positions[a].scale(1.0 - r1)
+            + self.positions[b].scale(r1 * (1.0 - r2))
+            + self.positions[c].scale(r1 * r2);
+
+        point - *o
+    }
+
+    /// Each emissive material's triangles become one light
+    fn emitters(&self, emitters: &mut Vec<Emitter>) {
+        for (id, material) in self.materials.iter().enumerate() {
+            if !material.is_emissive() {
+                continue;
+            }
+            let light = self.submesh(id as u32);
+            if light.triangles.is_empty() {
+                continue;
+            }
+            emitters.push(Emitter {
+                area: light.area(),
+                emission: material.average_emission(),
+                object: Arc::new(light),
+            });
+        }
+    }
+}
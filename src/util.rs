@@ -1,7 +1,35 @@
 use rand::distributions::{Distribution, Uniform};
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
 use crate::vec3::Vec3;
+use std::cell::RefCell;
 use std::f64;
 
+thread_local! {
+    // Every thread starts from the same seed, the renderer reseeds it for each
+    // sample so images do not depend on which thread rendered which pixel
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(0));
+}
+
+/// Restarts the random number generator of the current thread from `seed`
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+/// Runs `f` with the random number generator of the current thread
+pub fn with_rng<R, F: FnOnce(&mut SmallRng) -> R>(f: F) -> R {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+/// Combines a seed with an index into a new well distributed seed using SplitMix64
+/// https://prng.di.unimi.it/splitmix64.c
+pub fn mix_seed(seed: u64, index: u64) -> u64 {
+    let mut z = seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     if x < min { return min; }
     if x > max { return max; }
@@ -10,15 +38,13 @@ pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
 
 pub fn random_double() -> f64 {
     let between = Uniform::from(0.0..1.0);
-    let mut rng = rand::thread_rng();
-    between.sample(&mut rng)
+    with_rng(|rng| between.sample(rng))
 }
 
 pub fn random_double_range(min: f64, max: f64) -> f64 {
     if min.ne(&max) {
         let between = Uniform::from(min..max);
-        let mut rng = rand::thread_rng();
-        between.sample(&mut rng)
+        with_rng(|rng| between.sample(rng))
     } else {
         min
     }
@@ -26,8 +52,7 @@ pub fn random_double_range(min: f64, max: f64) -> f64 {
 
 pub fn random_int_range(min: i64, max: i64) -> i64 {
     let between = Uniform::from(min..max);
-    let mut rng = rand::thread_rng();
-    between.sample(&mut rng)
+    with_rng(|rng| between.sample(rng))
 }
 
 pub fn random_cosine_direction() -> Vec3 {
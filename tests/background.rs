@@ -0,0 +1,117 @@
+use ray_tracer::background::{Background, EnvironmentMap, GradientBackground, SolidBackground};
+use ray_tracer::scene_file::parse_scene;
+use ray_tracer::util;
+use ray_tracer::vec3::Vec3;
+
+use image::{Rgb, Rgb32FImage};
+use std::f64::consts::PI;
+
+fn close(a: Vec3, b: Vec3) -> bool {
+    (a - b).length() < 1e-9
+}
+
+/// A dim map with one bright pixel in the middle of the horizon
+fn bright_spot() -> Rgb32FImage {
+    let mut image = Rgb32FImage::from_pixel(64, 32, Rgb([0.1, 0.1, 0.1]));
+    image.put_pixel(32, 16, Rgb([1000.0, 1000.0, 1000.0]));
+    image
+}
+
+/// The direction of the brightest pixel among many looked at around the horizon
+fn brightest_direction(background: &dyn Background) -> Vec3 {
+    (0..3600)
+        .map(|i| {
+            let angle = (i as f64 / 10.0).to_radians();
+            Vec3::new(angle.cos(), -0.05, angle.sin())
+        })
+        .max_by(|a, b| background.value(a).x().partial_cmp(&background.value(b).x()).unwrap())
+        .unwrap()
+}
+
+#[test]
+fn solid_and_gradient_backgrounds() {
+    let solid = SolidBackground::new(0.1, 0.2, 0.3);
+    assert!(close(solid.value(&Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.1, 0.2, 0.3)));
+    assert!(solid.pdf().is_none());
+
+    let sky = GradientBackground::sky();
+    assert!(close(sky.value(&Vec3::new(0.0, -2.0, 0.0)), Vec3::new(1.0, 1.0, 1.0)));
+    assert!(close(sky.value(&Vec3::new(0.0, 2.0, 0.0)), Vec3::new(0.5, 0.7, 1.0)));
+    assert!(close(sky.value(&Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.75, 0.85, 1.0)));
+    assert!(sky.pdf().is_none());
+}
+
+#[test]
+fn environment_pdf_integrates_to_one() {
+    util::seed_random(1);
+    let environment = EnvironmentMap::from_image(&bright_spot(), 30.0, 1.0);
+    let pdf = environment.pdf().unwrap();
+
+    let n = 400_000;
+    let integral = (0..n).map(|_| pdf.value(&Vec3::random_unit_vector())).sum::<f64>() * 4.0 * PI / n as f64;
+    assert!((integral - 1.0).abs() < 0.03, "density integrates to {}", integral);
+
+    for _ in 0..1000 {
+        let v = pdf.generate();
+        assert!((v.length() - 1.0).abs() < 1e-9);
+        assert!(pdf.value(&v) > 0.0, "sampled direction {:?} has no density", v);
+    }
+}
+
+#[test]
+fn environment_samples_favour_bright_pixels() {
+    util::seed_random(2);
+    let environment = EnvironmentMap::from_image(&bright_spot(), 0.0, 1.0);
+    let pdf = environment.pdf().unwrap();
+    let bright = brightest_direction(&environment);
+
+    // The bright pixel gives off about seven eighths of the map's power, the
+    // rest is spread thinly over the other 2047 pixels
+    let n = 1000;
+    let on_spot = (0..n).filter(|_| environment.value(&pdf.generate()).x() > 100.0).count();
+    assert!((830..930).contains(&on_spot), "only {} of {} samples hit the bright pixel", on_spot, n);
+    assert!(pdf.value(&bright) > 100.0 * pdf.value(&Vec3::new(0.0, 1.0, 0.0)));
+}
+
+#[test]
+fn environment_rotation_and_intensity() {
+    let environment = EnvironmentMap::from_image(&bright_spot(), 0.0, 1.0);
+    let rotated = EnvironmentMap::from_image(&bright_spot(), 90.0, 2.0);
+
+    // Turning the map counterclockwise about +y carries the bright pixel with it
+    let d = brightest_direction(&environment);
+    let turned = Vec3::new(d.z(), d.y(), -d.x());
+    assert!(rotated.value(&turned).x() > 100.0, "bright pixel did not move to {:?}", turned);
+    assert!((rotated.value(&turned) - environment.value(&d).scale(2.0)).length() < 1e-3);
+    assert!((rotated.value(&Vec3::new(0.0, 1.0, 0.0)).x() - 0.2).abs() < 1e-6);
+
+    // The density follows the rotation too
+    let pdf = rotated.pdf().unwrap();
+    assert!(pdf.value(&turned) > 100.0 * pdf.value(&d));
+}
+
+#[test]
+fn scene_files_pick_a_background() {
+    let source = |background: &str| format!(r#"
+        background_color = [0.1, 0.2, 0.3]
+        {}
+
+        [camera]
+        lookfrom = [0.0, 0.0, 10.0]
+        lookat = [0.0, 0.0, 0.0]
+        vfov = 40.0
+    "#, background);
+    let up = Vec3::new(0.0, 1.0, 0.0);
+
+    let scene = parse_scene(&source(""), 10, 10).unwrap();
+    assert!(close(scene.background.value(&up), Vec3::new(0.1, 0.2, 0.3)));
+
+    let scene = parse_scene(&source(r#"background = { type = "gradient" }"#), 10, 10).unwrap();
+    assert!(close(scene.background.value(&up), Vec3::new(0.5, 0.7, 1.0)));
+
+    let scene = parse_scene(&source(r#"background = { type = "solid", color = [1.0, 0.0, 0.0] }"#), 10, 10).unwrap();
+    assert!(close(scene.background.value(&up), Vec3::new(1.0, 0.0, 0.0)));
+
+    let missing = r#"background = { type = "environment", path = "no_such_sky.hdr", rotation = 45.0 }"#;
+    assert!(parse_scene(&source(missing), 10, 10).is_err());
+}
@@ -0,0 +1,36 @@
+//! Fixtures shared by the integration tests, each test crate uses only some of them.
+#![allow(dead_code)]
+
+use ray_tracer::hittable::{HitRecord, Hittable};
+use ray_tracer::material::{Lambertian, Material};
+use ray_tracer::ray::Ray;
+use ray_tracer::texture::SolidTexture;
+use ray_tracer::vec3::Vec3;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+pub fn gray() -> Arc<dyn Material> {
+    Arc::new(Lambertian::new(SolidTexture::new(0.5, 0.5, 0.5)))
+}
+
+pub fn hit(object: &dyn Hittable, origin: Vec3, direction: Vec3) -> Option<HitRecord> {
+    hit_at(object, origin, direction, 0.0)
+}
+
+/// Like `hit` for a ray sent at `time`
+pub fn hit_at(object: &dyn Hittable, origin: Vec3, direction: Vec3, time: f64) -> Option<HitRecord> {
+    let mut rec = HitRecord::new();
+    if object.hit(&Ray::new(origin, direction, time), 0.001, f64::INFINITY, &mut rec) {
+        Some(rec)
+    } else {
+        None
+    }
+}
+
+/// A uniform estimate over all directions of the integral of the density
+/// `object` samples directions from `o` with
+pub fn pdf_integral(object: &dyn Hittable, o: &Vec3) -> f64 {
+    let n = 200_000;
+    (0..n).map(|_| object.pdf_value(o, &Vec3::random_unit_vector())).sum::<f64>() * 4.0 * PI / n as f64
+}
@@ -0,0 +1,44 @@
+use ray_tracer::renderer::Renderer;
+use ray_tracer::scenes::build_scene;
+use ray_tracer::tile::TileOrder;
+use ray_tracer::util;
+
+const WIDTH: usize = 48;
+const HEIGHT: usize = 32;
+const SAMPLES: usize = 4;
+
+fn render_cornell_box<F: FnOnce(&mut Renderer)>(seed: u64, configure: F) -> Vec<u8> {
+    util::seed_random(seed);
+    let scene = build_scene("Cornell Box", WIDTH, HEIGHT).unwrap();
+
+    let mut renderer = Renderer::new(scene, WIDTH, HEIGHT);
+    renderer.set_seed(seed);
+    configure(&mut renderer);
+    renderer.render_pass(SAMPLES);
+    renderer.snapshot()
+}
+
+#[test]
+fn renders_with_the_same_seed_are_identical() {
+    let first = render_cornell_box(7, |_| {});
+    let second = render_cornell_box(7, |_| {});
+    assert!(first == second, "two renders with the same seed differ");
+}
+
+#[test]
+fn renders_do_not_depend_on_threads_or_tiles() {
+    let first = render_cornell_box(7, |renderer| {
+        renderer.set_threads(1).unwrap();
+    });
+    let second = render_cornell_box(7, |renderer| {
+        renderer.set_threads(3).unwrap();
+        renderer.set_tile_size(5);
+        renderer.set_tile_order(TileOrder::Hilbert);
+    });
+    assert!(first == second, "the thread count or tiling changed the image");
+}
+
+#[test]
+fn renders_with_different_seeds_differ() {
+    assert!(render_cornell_box(7, |_| {}) != render_cornell_box(8, |_| {}));
+}
@@ -0,0 +1,66 @@
+use ray_tracer::hittable::HitRecord;
+use ray_tracer::material::{Dielectric, Material, ScatterRecord};
+use ray_tracer::ray::Ray;
+use ray_tracer::vec3::Vec3;
+
+const SAMPLES: usize = 100_000;
+
+/// Scatters a ray hitting a surface facing +z head on and returns the
+/// fraction of the scattered rays that were reflected.
+fn reflected_fraction(ref_idx: f64, front_face: bool) -> f64 {
+    let material = Dielectric::new(ref_idx);
+    let direction = if front_face { Vec3::new(0.0, 0.0, -1.0) } else { Vec3::new(0.0, 0.0, 1.0) };
+    let r_in = Ray::new(Vec3::new(0.0, 0.0, 0.0) - direction, direction, 0.0);
+
+    let mut rec = HitRecord::new();
+    rec.p = Vec3::zero();
+    rec.set_face_normal(&r_in, &Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(rec.front_face, front_face);
+
+    let mut reflected = 0;
+    for _ in 0..SAMPLES {
+        let mut srec = ScatterRecord::new();
+        assert!(material.scatter(&r_in, &mut rec, &mut srec));
+        assert!(srec.is_specular);
+
+        // A dielectric must not absorb or create energy
+        assert_eq!(srec.attenuation.x(), 1.0);
+        assert_eq!(srec.attenuation.y(), 1.0);
+        assert_eq!(srec.attenuation.z(), 1.0);
+
+        let out = srec.specular_ray.direction().unit_vector();
+        if out.dot(&direction) < 0.0 {
+            assert!((out + direction).length() < 1e-9, "reflection at normal incidence must go straight back");
+            reflected += 1;
+        } else {
+            assert!((out - direction).length() < 1e-9, "refraction at normal incidence must not bend");
+        }
+    }
+
+    reflected as f64 / SAMPLES as f64
+}
+
+fn normal_reflectance(ref_idx: f64) -> f64 {
+    let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
+    r0 * r0
+}
+
+#[test]
+fn dielectric_conserves_energy_entering_at_normal_incidence() {
+    let fraction = reflected_fraction(1.5, true);
+    let expected = normal_reflectance(1.5);
+    // Reflected and transmitted energy must add up to the incoming energy
+    assert!((fraction - expected).abs() < 0.005, "reflected {} expected {}", fraction, expected);
+}
+
+#[test]
+fn dielectric_conserves_energy_leaving_at_normal_incidence() {
+    let fraction = reflected_fraction(1.5, false);
+    let expected = normal_reflectance(1.5);
+    assert!((fraction - expected).abs() < 0.005, "reflected {} expected {}", fraction, expected);
+}
+
+#[test]
+fn dielectric_with_matching_index_is_transparent() {
+    assert_eq!(reflected_fraction(1.0, true), 0.0);
+}
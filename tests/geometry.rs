@@ -0,0 +1,187 @@
+mod common;
+
+use common::{gray, hit, hit_at, pdf_integral};
+use ray_tracer::aabb::AABB;
+use ray_tracer::aarect::{XYRect, XZRect, YZRect};
+use ray_tracer::bvh::BVHSplit;
+use ray_tracer::cube::Cube;
+use ray_tracer::hittable::Hittable;
+use ray_tracer::sphere::{MovingSphere, Sphere};
+use ray_tracer::triangle::Triangle;
+use ray_tracer::triangle_mesh::{TriangleMesh, TriangleMeshData};
+use ray_tracer::util;
+use ray_tracer::vec3::Vec3;
+
+
+fn assert_inside(name: &str, aabb_box: &AABB, p: &Vec3) {
+    for axis in 0..3 {
+        assert!(p[axis] >= aabb_box.min()[axis] - 1e-6 && p[axis] <= aabb_box.max()[axis] + 1e-6,
+                "{}: surface point {:?} is outside its box {:?} {:?}", name, p, aabb_box.min(), aabb_box.max());
+    }
+}
+
+/// Checks the box of `object` over `time0` to `time1` holds every point found
+/// by rays aimed at the box from far away and by rays towards light samples
+/// taken from `o`
+fn assert_box_contains_surface(name: &str, object: &dyn Hittable, o: Vec3, time0: f64, time1: f64) {
+    let mut aabb_box = AABB::new_max();
+    assert!(object.bounding_box(time0, time1, &mut aabb_box), "{}: no bounding box", name);
+    let size = aabb_box.max() - aabb_box.min();
+    assert!(size.x() > 0.0 && size.y() > 0.0 && size.z() > 0.0, "{}: box has no volume", name);
+
+    let center = (aabb_box.min() + aabb_box.max()).scale(0.5);
+    let mut hits = 0;
+    for _ in 0..10_000 {
+        let origin = center + Vec3::random_unit_vector().scale(10.0 * size.length());
+        let target = aabb_box.min() + size * Vec3::random();
+        if let Some(rec) = hit_at(object, origin, target - origin, util::random_double_range(time0, time1)) {
+            assert_inside(name, &aabb_box, &rec.p);
+            hits += 1;
+        }
+    }
+    assert!(hits > 0, "{}: no ray aimed at the box hit", name);
+
+    for _ in 0..10_000 {
+        let v = object.random(&o);
+        if let Some(rec) = hit_at(object, o, v, util::random_double_range(time0, time1)) {
+            assert_inside(name, &aabb_box, &rec.p);
+        }
+    }
+}
+
+/// Checks the density of `object`'s light samples from `o` integrates to one
+/// over all directions, and that every sampled direction has some density
+fn assert_pdf_integrates_to_one(name: &str, object: &dyn Hittable, o: Vec3) {
+    let integral = pdf_integral(object, &o);
+    assert!((integral - 1.0).abs() < 0.03, "{}: density integrates to {}", name, integral);
+
+    for _ in 0..1000 {
+        let v = object.random(&o);
+        assert!(object.pdf_value(&o, &v) > 0.0, "{}: sampled direction {:?} has no density", name, v);
+    }
+}
+
+#[test]
+fn sphere() {
+    util::seed_random(1);
+    let sphere = Sphere::new(&Vec3::new(0.0, 0.0, -3.0), 2.0, gray());
+    assert_box_contains_surface("sphere", &sphere, Vec3::zero(), 0.0, 1.0);
+    assert_pdf_integrates_to_one("sphere", &sphere, Vec3::zero());
+    // From inside every direction hits the sphere
+    assert_pdf_integrates_to_one("sphere from inside", &sphere, Vec3::new(0.5, 0.0, -3.0));
+
+    for _ in 0..1000 {
+        let v = sphere.random(&Vec3::zero());
+        assert!(hit(&sphere, Vec3::zero(), v).is_some(), "sampled direction {:?} misses", v);
+    }
+
+    // Only the directions that hit the sphere have any density
+    for _ in 0..1000 {
+        let v = Vec3::random_unit_vector();
+        let hits = hit(&sphere, Vec3::zero(), v).is_some();
+        assert_eq!(sphere.pdf_value(&Vec3::zero(), &v) > 0.0, hits, "direction {:?}", v);
+    }
+}
+
+#[test]
+fn moving_sphere() {
+    util::seed_random(2);
+    let sphere = MovingSphere::new(&Vec3::new(-0.5, 0.0, -3.0), &Vec3::new(0.5, 0.0, -3.0), 0.0, 1.0, 1.5, gray());
+    assert_box_contains_surface("moving sphere", &sphere, Vec3::zero(), 0.0, 1.0);
+    assert_pdf_integrates_to_one("moving sphere", &sphere, Vec3::zero());
+
+    // Directions toward where the sphere is at any time are sampled
+    for &time in &[0.0, 0.5, 1.0] {
+        let rec = hit_at(&sphere, Vec3::zero(), Vec3::new(-1.0 + 2.0 * time, 0.0, -3.0), time).unwrap();
+        assert!(sphere.pdf_value(&Vec3::zero(), &rec.p) > 0.0);
+    }
+}
+
+#[test]
+fn rects() {
+    util::seed_random(3);
+    let xy = XYRect::new(gray(), -1.0, 1.0, -1.0, 1.0, -1.0);
+    let xz = XZRect::new(gray(), -1.0, 1.0, -1.0, 1.0, 1.0);
+    let yz = YZRect::new(gray(), -1.0, 1.0, -1.0, 1.0, 1.0);
+
+    for (name, rect) in [("xy rect", &xy as &dyn Hittable), ("xz rect", &xz), ("yz rect", &yz)] {
+        assert_box_contains_surface(name, rect, Vec3::zero(), 0.0, 1.0);
+        assert_pdf_integrates_to_one(name, rect, Vec3::zero());
+        // Seen from behind the density is the same
+        assert_pdf_integrates_to_one(name, rect, Vec3::new(0.2, 1.5, -1.5));
+    }
+}
+
+#[test]
+fn triangle() {
+    util::seed_random(4);
+    let triangle = Triangle::new_flat(Vec3::new(-2.0, -2.0, -1.0), Vec3::new(2.0, -2.0, -1.0), Vec3::new(0.0, 2.0, -1.5),
+                                      gray());
+    assert!((triangle.area() - 0.5 * (Vec3::new(4.0, 0.0, 0.0).cross(&Vec3::new(2.0, 4.0, -0.5))).length()).abs() < 1e-9);
+    assert_box_contains_surface("triangle", &triangle, Vec3::zero(), 0.0, 1.0);
+    assert_pdf_integrates_to_one("triangle", &triangle, Vec3::zero());
+
+    for _ in 0..1000 {
+        let v = triangle.random(&Vec3::zero());
+        assert!(hit(&triangle, Vec3::zero(), v).is_some(), "sampled direction {:?} misses", v);
+    }
+}
+
+#[test]
+fn smooth_triangle_mesh() {
+    util::seed_random(6);
+    let (a, b, c) = (Vec3::new(-2.0, -2.0, -1.0), Vec3::new(2.0, -2.0, -1.0), Vec3::new(0.0, 2.0, -1.5));
+    let triangle = Triangle::new_flat(a, b, c, gray());
+    // Shading normals tilted well away from the face normal
+    let mesh = TriangleMesh::new(TriangleMeshData {
+        positions: vec![a, b, c],
+        normals: vec![Vec3::new(1.0, 0.0, 1.0).unit_vector(), Vec3::new(-1.0, 0.0, 1.0).unit_vector(),
+                      Vec3::new(0.0, 1.0, 1.0).unit_vector()],
+        triangles: vec![[0, 1, 2]],
+        materials: vec![gray()],
+        ..TriangleMeshData::default()
+    }, BVHSplit::Sah);
+
+    assert_pdf_integrates_to_one("smooth triangle mesh", &mesh, Vec3::zero());
+    // The density only depends on where the triangle is, not how it is shaded
+    for _ in 0..1000 {
+        let v = mesh.random(&Vec3::zero());
+        let (mesh_pdf, triangle_pdf) = (mesh.pdf_value(&Vec3::zero(), &v), triangle.pdf_value(&Vec3::zero(), &v));
+        assert!((mesh_pdf - triangle_pdf).abs() < 1e-9 * triangle_pdf, "mesh {} triangle {}", mesh_pdf, triangle_pdf);
+    }
+}
+
+#[test]
+fn closed_triangle_mesh() {
+    util::seed_random(7);
+    let (min, max) = (Vec3::new(-1.0, -1.0, -6.0), Vec3::new(1.0, 1.0, -4.0));
+    let positions = (0..8)
+        .map(|i| Vec3::new(if i & 1 == 0 { min.x() } else { max.x() },
+                           if i & 2 == 0 { min.y() } else { max.y() },
+                           if i & 4 == 0 { min.z() } else { max.z() }))
+        .collect();
+    let faces: [[u32; 4]; 6] = [[0, 1, 3, 2], [4, 6, 7, 5], [0, 4, 5, 1], [2, 3, 7, 6], [0, 2, 6, 4], [1, 5, 7, 3]];
+    let mesh = TriangleMesh::new(TriangleMeshData {
+        positions,
+        triangles: faces.iter().flat_map(|&[a, b, c, d]| vec![[a, b, c], [a, c, d]]).collect(),
+        materials: vec![gray()],
+        ..TriangleMeshData::default()
+    }, BVHSplit::Sah);
+
+    // Points are sampled on the hidden faces as well as the one facing the origin
+    assert_pdf_integrates_to_one("closed triangle mesh", &mesh, Vec3::zero());
+    let cube = Cube::new(min, max, gray());
+    for _ in 0..1000 {
+        let v = mesh.random(&Vec3::zero());
+        let (mesh_pdf, cube_pdf) = (mesh.pdf_value(&Vec3::zero(), &v), cube.pdf_value(&Vec3::zero(), &v));
+        assert!((mesh_pdf - cube_pdf).abs() < 1e-6 * cube_pdf, "mesh {} cube {}", mesh_pdf, cube_pdf);
+    }
+}
+
+#[test]
+fn cube() {
+    util::seed_random(5);
+    let cube = Cube::new(Vec3::new(-1.0, -1.0, -3.0), Vec3::new(1.0, 1.0, -1.0), gray());
+    assert_box_contains_surface("cube", &cube, Vec3::zero(), 0.0, 1.0);
+    assert_pdf_integrates_to_one("cube", &cube, Vec3::zero());
+}
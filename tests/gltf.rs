@@ -0,0 +1,193 @@
+use ray_tracer::gltf_scene::{load_gltf, GltfError};
+use ray_tracer::hittable::{HitRecord, Hittable};
+use ray_tracer::material::ScatterRecord;
+use ray_tracer::ray::Ray;
+use ray_tracer::vec3::Vec3;
+
+use std::fs;
+use std::path::PathBuf;
+
+/// A unit quad in the xy plane facing +z, moved in front of a camera at the
+/// origin by its node and lit by its emissive material
+const GLTF: &str = r#"{
+  "asset": { "version": "2.0" },
+  "scene": 0,
+  "scenes": [{ "nodes": [0, 1] }],
+  "nodes": [
+    { "mesh": 0, "translation": [0, 0, -3], "scale": [2, 2, 1] },
+    { "camera": 0 }
+  ],
+  "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.5, "znear": 0.1 } }],
+  "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 }] }],
+  "materials": [{
+    "emissiveFactor": [1, 0.5, 0.25],
+    "extensions": { "KHR_materials_emissive_strength": { "emissiveStrength": 4 } }
+  }],
+  "extensionsUsed": ["KHR_materials_emissive_strength"],
+  "accessors": [
+    { "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3", "min": [-0.5, -0.5, 0], "max": [0.5, 0.5, 0] },
+    { "bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR" }
+  ],
+  "bufferViews": [
+    { "buffer": 0, "byteOffset": 0, "byteLength": 48 },
+    { "buffer": 0, "byteOffset": 48, "byteLength": 12 }
+  ],
+  "buffers": [{ "byteLength": 60, "uri": "quad.bin" }]
+}"#;
+
+/// Writes the glTF and its buffer to their own directory and returns the glTF's path
+fn write_files() -> PathBuf {
+    write_quad("ray_tracer_gltf_test", [0, 1, 2, 0, 2, 3])
+}
+
+/// Writes the quad glTF with the given triangle indices into the directory `name`
+fn write_quad(name: &str, indices: [u16; 6]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let positions: [f32; 12] = [-0.5, -0.5, 0.0, 0.5, -0.5, 0.0, 0.5, 0.5, 0.0, -0.5, 0.5, 0.0];
+    let mut buffer: Vec<u8> = positions.iter().flat_map(|p| p.to_le_bytes()).collect();
+    buffer.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+
+    fs::write(dir.join("quad.bin"), buffer).unwrap();
+    fs::write(dir.join("quad.gltf"), GLTF).unwrap();
+    dir.join("quad.gltf")
+}
+
+#[test]
+fn nodes_materials_and_camera_are_imported() {
+    let path = write_files();
+    let scene = load_gltf(path.to_str().unwrap(), 100, 100).unwrap();
+
+    // The camera looks down -z at the middle of the quad
+    let r = scene.camera.get_ray(0.5, 0.5);
+    let mut rec = HitRecord::new();
+    assert!(scene.objects.hit(&r, 0.001, f64::INFINITY, &mut rec));
+    assert!((rec.p.z() + 3.0).abs() < 1e-9, "hit at {:?}", rec.p);
+    assert!(rec.front_face);
+
+    let emitted = rec.mat.emitted(&r, &rec, rec.u, rec.v, &rec.p);
+    assert_eq!((emitted.x(), emitted.y(), emitted.z()), (4.0, 2.0, 1.0));
+
+    // The scaled quad is a light with an area of 4, seen off its diagonal
+    // where only one of its triangles is in the way
+    let o = Vec3::zero();
+    let v = Vec3::new(0.1, -0.2, -1.0);
+    let distance_squared = 9.0 * v.length_squared();
+    let cosine = 1.0 / v.length();
+    let pdf = scene.lights.as_ref().unwrap().pdf_value(&o, &v);
+    assert!((pdf - distance_squared / (cosine * 4.0)).abs() < 1e-9, "pdf {}", pdf);
+    let background = scene.background.value(&v);
+    assert_eq!((background.x(), background.y()), (0.0, 0.0));
+
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn indices_past_the_vertices_are_errors() {
+    let path = write_quad("ray_tracer_gltf_bad_index_test", [0, 1, 2, 0, 2, 9]);
+    let error = load_gltf(path.to_str().unwrap(), 100, 100).err().unwrap();
+    assert!(matches!(error, GltfError::Invalid(_)), "{}", error);
+
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn base_color_factor_tints_the_texture() {
+    let path = write_quad("ray_tracer_gltf_texture_test", [0, 1, 2, 0, 2, 3]);
+    let dir = path.parent().unwrap().to_path_buf();
+    image::RgbImage::from_pixel(1, 1, image::Rgb([255, 255, 255])).save(dir.join("white.png")).unwrap();
+    let textured = GLTF
+        .replace(r#""emissiveFactor": [1, 0.5, 0.25],
+    "extensions": { "KHR_materials_emissive_strength": { "emissiveStrength": 4 } }"#,
+                 r#""pbrMetallicRoughness": { "baseColorTexture": { "index": 0 }, "baseColorFactor": [0.5, 0.25, 1, 1] }"#)
+        .replace(r#""accessors""#, r#""textures": [{ "source": 0 }],
+  "images": [{ "uri": "white.png" }],
+  "accessors""#);
+    let textured_path = dir.join("textured.gltf");
+    fs::write(&textured_path, textured).unwrap();
+    let scene = load_gltf(textured_path.to_str().unwrap(), 100, 100).unwrap();
+
+    let r = scene.camera.get_ray(0.5, 0.5);
+    let mut rec = HitRecord::new();
+    assert!(scene.objects.hit(&r, 0.001, f64::INFINITY, &mut rec));
+    let mut srec = ScatterRecord::new();
+    let material = rec.mat.clone();
+    assert!(material.scatter(&r, &mut rec, &mut srec));
+    let attenuation = srec.attenuation;
+    assert_eq!((attenuation.x(), attenuation.y(), attenuation.z()), (0.5, 0.25, 1.0));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A tent of two flat triangles meeting along the y axis and facing +z, and a
+/// quad off to the side with its own normals, in the given order
+fn mixed_normals_gltf(tent_first: bool) -> String {
+    let tent = r#"{ "attributes": { "POSITION": 0 }, "indices": 1 }"#;
+    let quad = r#"{ "attributes": { "POSITION": 2, "NORMAL": 3 }, "indices": 4 }"#;
+    let primitives = if tent_first { [tent, quad] } else { [quad, tent] };
+    format!(r#"{{
+  "asset": {{ "version": "2.0" }},
+  "scene": 0,
+  "scenes": [{{ "nodes": [0] }}],
+  "nodes": [{{ "mesh": 0 }}],
+  "meshes": [{{ "primitives": [{}, {}] }}],
+  "accessors": [
+    {{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3", "min": [-1, -1, -1], "max": [1, 1, 0] }},
+    {{ "bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR" }},
+    {{ "bufferView": 2, "componentType": 5126, "count": 4, "type": "VEC3", "min": [9.5, -0.5, -5], "max": [10.5, 0.5, -5] }},
+    {{ "bufferView": 3, "componentType": 5126, "count": 4, "type": "VEC3" }},
+    {{ "bufferView": 4, "componentType": 5123, "count": 6, "type": "SCALAR" }}
+  ],
+  "bufferViews": [
+    {{ "buffer": 0, "byteOffset": 0, "byteLength": 48 }},
+    {{ "buffer": 0, "byteOffset": 48, "byteLength": 12 }},
+    {{ "buffer": 0, "byteOffset": 60, "byteLength": 48 }},
+    {{ "buffer": 0, "byteOffset": 108, "byteLength": 48 }},
+    {{ "buffer": 0, "byteOffset": 156, "byteLength": 12 }}
+  ],
+  "buffers": [{{ "byteLength": 168, "uri": "mixed.bin" }}]
+}}"#, primitives[0], primitives[1])
+}
+
+#[test]
+fn primitives_without_normals_stay_flat() {
+    let dir = std::env::temp_dir().join(format!("ray_tracer_gltf_mixed_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let tent_positions: [f32; 12] = [0.0, -1.0, 0.0, 0.0, 1.0, 0.0, -1.0, 0.0, -1.0, 1.0, 0.0, -1.0];
+    let tent_indices: [u16; 6] = [2, 0, 1, 0, 3, 1];
+    let quad_positions: [f32; 12] = [9.5, -0.5, -5.0, 10.5, -0.5, -5.0, 10.5, 0.5, -5.0, 9.5, 0.5, -5.0];
+    let quad_normals: [f32; 12] = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+    let quad_indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
+    let mut buffer: Vec<u8> = tent_positions.iter().flat_map(|p| p.to_le_bytes()).collect();
+    buffer.extend(tent_indices.iter().flat_map(|i| i.to_le_bytes()));
+    buffer.extend(quad_positions.iter().chain(&quad_normals).flat_map(|p| p.to_le_bytes()));
+    buffer.extend(quad_indices.iter().flat_map(|i| i.to_le_bytes()));
+    fs::write(dir.join("mixed.bin"), buffer).unwrap();
+
+    let down = Vec3::new(0.0, 0.0, -1.0);
+    let left = Vec3::new(-1.0, 0.0, 1.0).unit_vector();
+    let right = Vec3::new(1.0, 0.0, 1.0).unit_vector();
+
+    for &tent_first in &[true, false] {
+        let path = dir.join("mixed.gltf");
+        fs::write(&path, mixed_normals_gltf(tent_first)).unwrap();
+        let scene = load_gltf(path.to_str().unwrap(), 100, 100).unwrap();
+
+        // Each side of the tent has its own face normal right up to the shared ridge
+        for &(x, y, normal) in &[(-0.5, 0.0, left), (-0.05, 0.5, left), (0.5, 0.3, right), (0.05, -0.5, right)] {
+            let mut rec = HitRecord::new();
+            let r = Ray::new(Vec3::new(x, y, 5.0), down, 0.0);
+            assert!(scene.objects.hit(&r, 0.001, f64::INFINITY, &mut rec));
+            assert!((rec.normal - normal).length() < 1e-6, "normal {:?} at ({}, {})", rec.normal, x, y);
+        }
+
+        let mut rec = HitRecord::new();
+        let r = Ray::new(Vec3::new(10.2, 0.1, 5.0), down, 0.0);
+        assert!(scene.objects.hit(&r, 0.001, f64::INFINITY, &mut rec));
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-6, "normal {:?}", rec.normal);
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use common::{gray, hit};
+use ray_tracer::aabb::AABB;
+use ray_tracer::aarect::XZRect;
+use ray_tracer::cube::Cube;
+use ray_tracer::bvh::{BVHNode, BVHSplit};
+use ray_tracer::flat_bvh::FlatBVH;
+use ray_tracer::hittable::{FlipFace, HitRecord, Hittable, RotateY, Translate};
+use ray_tracer::hittable_list::HittableList;
+use ray_tracer::mesh::Mesh;
+use ray_tracer::ray::Ray;
+use ray_tracer::scene_file::parse_scene;
+use ray_tracer::util;
+use ray_tracer::vec3::Vec3;
+
+use std::f64::consts::PI;
+use std::fs;
+use std::sync::Arc;
+
+/// The box around the hits of rays shot from far away at random points of `aabb_box`
+fn sampled_extent(object: &dyn Hittable, aabb_box: &AABB) -> AABB {
+    let center = (aabb_box.min() + aabb_box.max()).scale(0.5);
+    let mut extent = AABB::new_max();
+    for _ in 0..20_000 {
+        let origin = center + Vec3::random_unit_vector().scale(1000.0);
+        let target = aabb_box.min() + (aabb_box.max() - aabb_box.min()) * Vec3::random();
+        if let Some(rec) = hit(object, origin, target - origin) {
+            extent.add_point(&rec.p);
+        }
+    }
+    extent
+}
+
+#[test]
+fn rotate_y_bounding_box_fits_sampled_hits() {
+    util::seed_random(1);
+    for &angle in &[0.0, 15.0, 45.0, 90.0, 137.0, -30.0] {
+        let cube = Cube::new(Vec3::new(265.0, 0.0, 295.0), Vec3::new(430.0, 330.0, 460.0), gray());
+        let rotated = RotateY::new(cube, angle);
+
+        let mut aabb_box = AABB::new_max();
+        assert!(rotated.bounding_box(0.0, 1.0, &mut aabb_box));
+        let extent = sampled_extent(&rotated, &aabb_box);
+
+        for axis in 0..3 {
+            let (min, max) = (aabb_box.min()[axis], aabb_box.max()[axis]);
+            assert!(min.is_finite() && max.is_finite(), "infinite box at {} degrees", angle);
+            assert!(extent.min()[axis] >= min - 1e-6 && extent.max()[axis] <= max + 1e-6,
+                    "hit outside the box on axis {} at {} degrees", axis, angle);
+            assert!(extent.min()[axis] - min < 2.0 && max - extent.max()[axis] < 2.0,
+                    "box is loose on axis {} at {} degrees: {:?} {:?} around {:?} {:?}",
+                    axis, angle, aabb_box.min(), aabb_box.max(), extent.min(), extent.max());
+        }
+    }
+}
+
+#[test]
+fn rotate_y_keeps_which_face_was_hit() {
+    let rotated = RotateY::new(Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0), gray()), 30.0);
+
+    let outside = hit(&rotated, Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)).unwrap();
+    assert!(outside.front_face);
+    assert!(outside.normal.z() > 0.0);
+
+    let inside = hit(&rotated, Vec3::zero(), Vec3::new(0.0, 0.0, -1.0)).unwrap();
+    assert!(!inside.front_face);
+    assert!(inside.normal.z() > 0.0);
+}
+
+#[test]
+fn rotate_y_light_samples_its_solid_angle() {
+    util::seed_random(2);
+    // A ceiling light off to the side, swung around the y axis
+    let light = RotateY::new(XZRect::new(gray(), 0.5, 2.5, -1.0, 1.0, 1.0), 60.0);
+    let o = Vec3::zero();
+
+    let n = 200_000;
+    let integral = (0..n).map(|_| light.pdf_value(&o, &Vec3::random_unit_vector())).sum::<f64>() * 4.0 * PI / n as f64;
+    assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+
+    for _ in 0..1000 {
+        let v = light.random(&o);
+        assert!(hit(&light, o, v).is_some(), "sampled direction {:?} misses", v);
+        assert!(light.pdf_value(&o, &v) > 0.0);
+    }
+}
+
+/// Estimates the solid angle `light` covers from `o` as the mean of `1 / pdf`
+/// over directions it samples, which is exact in expectation for any sampler
+/// whose density matches its `pdf_value`
+fn sampled_solid_angle(light: &dyn Hittable, o: &Vec3) -> f64 {
+    let n = 20_000;
+    let sum: f64 = (0..n).map(|_| {
+        let v = light.random(o);
+        let pdf = light.pdf_value(o, &v);
+        assert!(pdf > 0.0, "sampled direction {:?} has no density", v);
+        1.0 / pdf
+    }).sum();
+    sum / n as f64
+}
+
+/// The solid angle of an `a` by `b` rectangle seen from `d` away along its center's normal
+fn rectangle_solid_angle(a: f64, b: f64, d: f64) -> f64 {
+    4.0 * (a * b / ((a * a + 4.0 * d * d) * (b * b + 4.0 * d * d)).sqrt()).asin()
+}
+
+fn assert_solid_angle(name: &str, light: &dyn Hittable, expected: f64) {
+    let estimate = sampled_solid_angle(light, &Vec3::zero());
+    assert!((estimate / expected - 1.0).abs() < 0.03, "{}: sampled {} expected {}", name, estimate, expected);
+}
+
+#[test]
+fn wrappers_sample_their_object_solid_angle() {
+    util::seed_random(3);
+    // Every light is a 2 by 2 square 1 above the origin, seen face on
+    let square = rectangle_solid_angle(2.0, 2.0, 1.0);
+    let ceiling = || Arc::new(XZRect::new(gray(), -1.0, 1.0, -1.0, 1.0, 1.0));
+
+    assert_solid_angle("flip face", &FlipFace::new(ceiling()), square);
+    assert_solid_angle("translate", &Translate::new(XZRect::new(gray(), -3.0, -1.0, -1.0, 1.0, 0.0), Vec3::new(2.0, 1.0, 0.0)),
+                       square);
+    assert_solid_angle("rotate y", &RotateY::new(ceiling(), 45.0), square);
+    // Only the bottom face of the cube can be seen from below it
+    assert_solid_angle("cube", &Cube::new(Vec3::new(-1.0, 1.0, -1.0), Vec3::new(1.0, 3.0, 1.0), gray()), square);
+
+    // A square above and one below, each covering the same solid angle
+    let floor: Arc<dyn Hittable> = Arc::new(XZRect::new(gray(), -1.0, 1.0, -1.0, 1.0, -1.0));
+    let mut objects: Vec<Arc<dyn Hittable>> = vec![ceiling(), floor];
+    assert_solid_angle("flat bvh", &FlatBVH::new(&objects, 0.0, 1.0, BVHSplit::Sah), 2.0 * square);
+    assert_solid_angle("bvh", &BVHNode::new(&mut objects, 0, 2, 0.0, 1.0), 2.0 * square);
+}
+
+#[test]
+fn mesh_samples_its_solid_angle() {
+    util::seed_random(4);
+    let dir = std::env::temp_dir().join(format!("ray_tracer_light_mesh_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("square.obj");
+    fs::write(&path, "v -1 1 -1\nv 1 1 -1\nv 1 1 1\nv -1 1 1\nf 1 2 3\nf 1 3 4\n").unwrap();
+
+    let mesh = Mesh::new_from_obj(path.to_str().unwrap(), &Vec3::zero(), 1.0, false, Some(gray()), BVHSplit::Sah).unwrap();
+    assert_solid_angle("mesh", &mesh, rectangle_solid_angle(2.0, 2.0, 1.0));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn empty_bvhs_hit_nothing() {
+    let r = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    for &split in &[BVHSplit::Median, BVHSplit::Sah] {
+        let bvh = BVHNode::from_list_with_split(&mut HittableList::new(), 0.0, 1.0, split);
+        assert!(!bvh.hit(&r, 0.001, f64::INFINITY, &mut HitRecord::new()));
+        assert!(!bvh.bounding_box(0.0, 1.0, &mut AABB::new_max()));
+        assert_eq!(bvh.pdf_value(&Vec3::zero(), &r.direction()), 0.0);
+
+        let flat = FlatBVH::from_list(&HittableList::new(), 0.0, 1.0, split);
+        assert!(!flat.hit(&r, 0.001, f64::INFINITY, &mut HitRecord::new()));
+        assert!(!flat.bounding_box(0.0, 1.0, &mut AABB::new_max()));
+        assert_eq!(flat.pdf_value(&Vec3::zero(), &r.direction()), 0.0);
+        assert!(flat.random(&Vec3::zero()).length() > 0.0);
+    }
+
+    let scene = parse_scene(r#"
+        [camera]
+        lookfrom = [0.0, 0.0, 10.0]
+        lookat = [0.0, 0.0, 0.0]
+        vfov = 40.0
+
+        [[objects]]
+        type = "bvh"
+        objects = []
+
+        [[lights]]
+        type = "instances"
+        instances = []
+        object = { type = "sphere", center = [0.0, 0.0, -5.0], radius = 1.0 }
+    "#, 100, 100).unwrap();
+    assert!(!scene.objects.hit(&r, 0.001, f64::INFINITY, &mut HitRecord::new()));
+    assert_eq!(scene.lights.unwrap().pdf_value(&Vec3::zero(), &r.direction()), 0.0);
+}
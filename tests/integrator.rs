@@ -0,0 +1,138 @@
+use ray_tracer::background::SolidBackground;
+use ray_tracer::hittable::FlipFace;
+use ray_tracer::hittable_list::HittableList;
+use ray_tracer::integrator::{Integrator, PathDepth};
+use ray_tracer::material::{Lambertian, Metal};
+use ray_tracer::ray::Ray;
+use ray_tracer::scenes::{build_scene, Scene, EARTH_MAP};
+use ray_tracer::sphere::Sphere;
+use ray_tracer::texture::SolidTexture;
+use ray_tracer::vec3::Vec3;
+use ray_tracer::{ray_color, util};
+
+use std::path::Path;
+use std::sync::Arc;
+
+/// The mean and variance of the brightness of `n` samples through the image point `u`, `v`
+fn sample_stats(integrator: Integrator, scene: &Scene, u: f64, v: f64, n: usize) -> (f64, f64) {
+    sample_stats_with_depth(integrator, scene, u, v, n, PathDepth::default())
+}
+
+fn sample_stats_with_depth(integrator: Integrator, scene: &Scene, u: f64, v: f64, n: usize,
+                           depth: PathDepth) -> (f64, f64) {
+    let samples: Vec<f64> = (0..n)
+        .map(|_| {
+            let color = integrator.ray_color(&scene.camera.get_ray(u, v), &*scene.background, &scene.objects,
+                                             scene.lights.as_deref(), depth);
+            (color.x() + color.y() + color.z()) / 3.0
+        })
+        .collect();
+    let mean = samples.iter().sum::<f64>() / n as f64;
+    let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+    (mean, variance)
+}
+
+#[test]
+fn next_event_estimation_converges_to_the_mixture_with_less_noise() {
+    util::seed_random(3);
+    let scene = build_scene("Cornell Box", 100, 100).unwrap();
+    let n = 2000;
+
+    // Points on the back wall, the floor, the ceiling and both side walls
+    for &(u, v) in &[(0.5, 0.5), (0.5, 0.15), (0.5, 0.9), (0.1, 0.5), (0.9, 0.5)] {
+        let (nee_mean, nee_variance) = sample_stats(Integrator::NextEvent, &scene, u, v, n);
+        let (mixture_mean, mixture_variance) = sample_stats(Integrator::Mixture, &scene, u, v, n);
+
+        // Both are unbiased, so their means differ by no more than their noise allows
+        let error = ((nee_variance + mixture_variance) / n as f64).sqrt();
+        assert!((nee_mean - mixture_mean).abs() < 4.0 * error,
+                "at ({}, {}) next event mean {} and mixture mean {} differ by more than {}",
+                u, v, nee_mean, mixture_mean, 4.0 * error);
+        assert!(nee_variance < 0.5 * mixture_variance,
+                "at ({}, {}) next event variance {} is not well below the mixture's {}",
+                u, v, nee_variance, mixture_variance);
+    }
+}
+
+#[test]
+fn sky_lit_scenes_sample_the_material_only() {
+    util::seed_random(1);
+    let mut world = HittableList::new();
+    world.add(Arc::new(Sphere::new(&Vec3::zero(), 1.0, Arc::new(Lambertian::new(SolidTexture::new(0.5, 0.5, 0.5))))));
+    let sky = SolidBackground::new(1.0, 1.0, 1.0);
+
+    // Every ray leaving a lone convex object reaches the sky, so each sample
+    // of a cosine sampled diffuse surface is exactly its albedo times the sky
+    for _ in 0..1000 {
+        let r = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::random_in_unit_disk().scale(0.5) - Vec3::new(0.0, 0.0, 5.0), 0.0);
+        let color = ray_color(&r, &sky, &world, None, PathDepth::default());
+        assert!((color - Vec3::new(0.5, 0.5, 0.5)).length() < 1e-9, "color {:?}", color);
+        let color = Integrator::Mixture.ray_color(&r, &sky, &world, None, PathDepth::default());
+        assert!((color - Vec3::new(0.5, 0.5, 0.5)).length() < 1e-9, "color {:?}", color);
+    }
+}
+
+#[test]
+fn book_scenes_are_built_with_and_without_lights() {
+    util::seed_random(2);
+    assert!(build_scene("Book 1", 40, 30).unwrap().lights.is_none());
+    let scene = build_scene("Book 2", 40, 40).unwrap();
+    assert!(scene.lights.is_some());
+
+    // The earth map is not in the repository, Book 2 still builds and reports it
+    let expected: Vec<&str> = if Path::new(EARTH_MAP).exists() { vec![] } else { vec![EARTH_MAP] };
+    assert_eq!(scene.missing_images, expected);
+    assert!(build_scene("Cornell Box", 40, 40).unwrap().missing_images.is_empty());
+}
+
+#[test]
+fn russian_roulette_keeps_the_mean() {
+    util::seed_random(4);
+    let scene = build_scene("Cornell Box", 100, 100).unwrap();
+    let n = 2000;
+    let full = PathDepth { min: usize::MAX, ..PathDepth::default() };
+    let roulette = PathDepth { min: 0, ..PathDepth::default() };
+
+    for &integrator in &[Integrator::NextEvent, Integrator::Mixture] {
+        let (full_mean, full_variance) = sample_stats_with_depth(integrator, &scene, 0.5, 0.15, n, full);
+        let (roulette_mean, roulette_variance) = sample_stats_with_depth(integrator, &scene, 0.5, 0.15, n, roulette);
+
+        let error = ((full_variance + roulette_variance) / n as f64).sqrt();
+        assert!((full_mean - roulette_mean).abs() < 4.0 * error,
+                "{:?}: full length mean {} and roulette mean {} differ by more than {}",
+                integrator, full_mean, roulette_mean, 4.0 * error);
+    }
+}
+
+#[test]
+fn paths_stop_at_the_max_depth() {
+    util::seed_random(5);
+    let scene = build_scene("Cornell Box", 100, 100).unwrap();
+    let depth = |max| PathDepth { max, ..PathDepth::default() };
+    let light = scene.camera.get_ray(0.5, 0.85);
+    let wall = scene.camera.get_ray(0.5, 0.5);
+
+    for &integrator in &[Integrator::NextEvent, Integrator::Mixture] {
+        let color = |r, max| integrator.ray_color(r, &*scene.background, &scene.objects, scene.lights.as_deref(), depth(max));
+        assert_eq!(color(&light, 0).x(), 0.0);
+        // With a single bounce only light seen straight from the camera arrives
+        assert!(color(&light, 1).x() > 1.0);
+        assert_eq!(color(&wall, 1).x(), 0.0);
+        assert!((0..100).any(|_| color(&wall, 2).x() > 0.0));
+    }
+}
+
+#[test]
+fn long_paths_do_not_overflow_the_stack() {
+    // A ray caught inside a perfect mirror bounces until the max depth
+    let mut world = HittableList::new();
+    world.add(Arc::new(FlipFace::new(Arc::new(Sphere::new(&Vec3::zero(), 1.0,
+                                                           Arc::new(Metal::new(&Vec3::new(1.0, 1.0, 1.0), 0.0)))))));
+    let sky = SolidBackground::new(1.0, 1.0, 1.0);
+    let r = Ray::new(Vec3::zero(), Vec3::new(0.3, 0.4, 0.5), 0.0);
+    let depth = PathDepth { max: 50_000, min: usize::MAX };
+
+    for &integrator in &[Integrator::NextEvent, Integrator::Mixture] {
+        assert_eq!(integrator.ray_color(&r, &sky, &world, None, depth).x(), 0.0);
+    }
+}
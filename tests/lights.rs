@@ -0,0 +1,137 @@
+mod common;
+
+use common::{gray, hit, pdf_integral};
+use ray_tracer::aarect::{XYRect, XZRect};
+use ray_tracer::cube::Cube;
+use ray_tracer::hittable::{FlipFace, Hittable, RotateY, Translate};
+use ray_tracer::hittable_list::HittableList;
+use ray_tracer::lights::{Lights, LightWeighting};
+use ray_tracer::material::{DiffuseLight, Material};
+use ray_tracer::scene_file::{parse_scene, SceneFileError};
+use ray_tracer::scenes::cornell_box;
+use ray_tracer::sphere::Sphere;
+use ray_tracer::texture::SolidTexture;
+use ray_tracer::transform::Transform;
+use ray_tracer::util;
+use ray_tracer::vec3::Vec3;
+
+use std::sync::Arc;
+
+fn light(brightness: f64) -> Arc<dyn Material> {
+    Arc::new(DiffuseLight::new(SolidTexture::new(brightness, brightness, brightness)))
+}
+
+#[test]
+fn cornell_box_light_is_found() {
+    util::seed_random(1);
+    let lights = Lights::from_objects(&cornell_box(), LightWeighting::Power);
+    assert_eq!(lights.len(), 1);
+
+    // Every sample from the middle of the floor heads for the ceiling light
+    let o = Vec3::new(278.0, 1.0, 278.0);
+    for _ in 0..1000 {
+        let v = lights.random(&o);
+        let p = o + v;
+        assert!((p.y() - 554.0).abs() < 1e-9 && (213.0..=343.0).contains(&p.x()) && (227.0..=332.0).contains(&p.z()),
+                "sampled point {:?} is not on the light", p);
+        assert!(lights.pdf_value(&o, &v) > 0.0);
+    }
+    assert_eq!(lights.pdf_value(&o, &Vec3::new(1.0, 0.0, 0.0)), 0.0);
+}
+
+#[test]
+fn lights_are_weighted_by_power_or_area() {
+    let mut world = HittableList::new();
+    // A small bright light and a large dim one with twice its power
+    world.add(Arc::new(XZRect::new(light(8.0), -1.0, 1.0, -1.0, 1.0, 5.0)));
+    world.add(Arc::new(XYRect::new(light(2.0), -4.0, 4.0, -2.0, 2.0, -5.0)));
+    world.add(Arc::new(Sphere::new(&Vec3::zero(), 1.0, gray())));
+
+    let by_power = Lights::from_objects(&world, LightWeighting::Power);
+    assert_eq!(by_power.len(), 2);
+    assert!((by_power.probability(0) - 1.0 / 3.0).abs() < 1e-9);
+    assert!((by_power.probability(1) - 2.0 / 3.0).abs() < 1e-9);
+
+    let by_area = Lights::from_objects(&world, LightWeighting::Area);
+    assert!((by_area.probability(0) - 4.0 / 36.0).abs() < 1e-9);
+    assert!((by_area.probability(1) - 32.0 / 36.0).abs() < 1e-9);
+
+    util::seed_random(2);
+    let o = Vec3::new(0.0, 2.0, 0.0);
+    for lights in &[by_power, by_area] {
+        let integral = pdf_integral(lights, &o);
+        assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+    }
+}
+
+#[test]
+fn wrapped_lights_are_found_in_place() {
+    util::seed_random(3);
+    let mut world = HittableList::new();
+    let panel: Arc<dyn Hittable> = Arc::new(FlipFace::new(Arc::new(XZRect::new(light(4.0), -1.0, 1.0, -1.0, 1.0, 0.0))));
+    world.add(Arc::new(Translate::new(RotateY::new(panel.clone(), 30.0), Vec3::new(0.0, 3.0, 0.0))));
+    world.add(Arc::new(Transform::new(panel)
+        .scale(Vec3::new(2.0, 1.0, 2.0)).unwrap()
+        .translate(Vec3::new(0.0, -3.0, 0.0)).unwrap()));
+    // All six sides of a glowing cube
+    world.add(Arc::new(Translate::new(Cube::new(Vec3::zero(), Vec3::new(1.0, 1.0, 1.0), light(1.0)),
+                                      Vec3::new(4.0, 0.0, 0.0))));
+
+    let lights = Lights::from_objects(&world, LightWeighting::Area);
+    assert_eq!(lights.len(), 8);
+    // The scaled panel's area grows by the cube root of the squared volume change
+    assert!((lights.probability(1) / lights.probability(0) - 16f64.powf(1.0 / 3.0)).abs() < 1e-9);
+
+    let o = Vec3::new(0.5, 0.5, 0.5);
+    for _ in 0..1000 {
+        let v = lights.random(&o);
+        assert!(hit(&world, o, v).is_some(), "sampled direction {:?} misses", v);
+        assert!(lights.pdf_value(&o, &v) > 0.0);
+    }
+    let integral = pdf_integral(&lights, &o);
+    assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+}
+
+#[test]
+fn scene_files_collect_lights_unless_listed() {
+    let source = |lights: &str| format!(r#"
+        light_weighting = "area"
+
+        [camera]
+        lookfrom = [0.0, 0.0, 10.0]
+        lookat = [0.0, 0.0, 0.0]
+        vfov = 40.0
+
+        [[objects]]
+        type = "xz_rect"
+        x0 = -1.0
+        x1 = 1.0
+        z0 = -1.0
+        z1 = 1.0
+        k = 5.0
+        material = {{ type = "diffuse_light", emit = [4.0, 4.0, 4.0] }}
+        {}
+    "#, lights);
+    let o = Vec3::zero();
+
+    let scene = parse_scene(&source(""), 100, 100).unwrap();
+    assert!(scene.lights.as_ref().unwrap().pdf_value(&o, &Vec3::new(0.0, 1.0, 0.0)) > 0.0);
+    assert_eq!(scene.lights.as_ref().unwrap().pdf_value(&o, &Vec3::new(0.0, -1.0, 0.0)), 0.0);
+
+    let listed = r#"
+        [[lights]]
+        type = "xz_rect"
+        x0 = -1.0
+        x1 = 1.0
+        z0 = -1.0
+        z1 = 1.0
+        k = -5.0
+    "#;
+    let scene = parse_scene(&source(listed), 100, 100).unwrap();
+    assert_eq!(scene.lights.as_ref().unwrap().pdf_value(&o, &Vec3::new(0.0, 1.0, 0.0)), 0.0);
+    assert!(scene.lights.as_ref().unwrap().pdf_value(&o, &Vec3::new(0.0, -1.0, 0.0)) > 0.0);
+
+    // Only the listed lights may leave out their material
+    let unlit = source("").replace(r#"material = { type = "diffuse_light", emit = [4.0, 4.0, 4.0] }"#, "");
+    assert!(matches!(parse_scene(&unlit, 100, 100), Err(SceneFileError::MissingMaterial)));
+}
@@ -0,0 +1,257 @@
+use ray_tracer::hittable::{HitRecord, Hittable};
+use ray_tracer::material::ScatterRecord;
+use ray_tracer::mesh::{Mesh, MeshError};
+use ray_tracer::ray::Ray;
+use ray_tracer::vec3::Vec3;
+
+use std::fs;
+use std::path::PathBuf;
+
+const OBJ: &str = "\
+mtllib quads.mtl
+o quads
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 2 0 0
+v 3 0 0
+v 3 1 0
+v 2 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+usemtl lamp
+f 1/1 2/2 3/3
+f 1/1 3/3 4/4
+usemtl mirror
+f 5/1 6/2 7/3
+f 5/1 7/3 8/4
+";
+
+const MTL: &str = "\
+newmtl lamp
+Kd 0 0 0
+Ke 2 3 4
+newmtl mirror
+Kd 0.1 0.1 0.1
+Ks 0.9 0.8 0.7
+Ns 1000000
+";
+
+/// Writes the OBJ and MTL files to their own directory and returns the OBJ's path
+fn write_files() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ray_tracer_mesh_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("quads.obj"), OBJ).unwrap();
+    fs::write(dir.join("quads.mtl"), MTL).unwrap();
+    dir.join("quads.obj")
+}
+
+/// Shoots a ray straight down the z axis at `(x, y)` on the quads
+fn hit(mesh: &Mesh, x: f64, y: f64) -> (Ray, HitRecord) {
+    let r = Ray::new(Vec3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    let mut rec = HitRecord::new();
+    assert!(mesh.hit(&r, 0.001, f64::INFINITY, &mut rec), "missed the mesh at ({}, {})", x, y);
+    (r, rec)
+}
+
+#[test]
+fn mtl_materials_are_assigned_per_face_with_uvs() {
+    let path = write_files();
+    let mesh = Mesh::new_from_obj(path.to_str().unwrap(), &Vec3::zero(), 1.0, false, None, Default::default()).unwrap();
+
+    // Both triangles of the first quad use the emissive material and interpolate its texture coordinates
+    for &(x, y) in &[(0.75, 0.25), (0.25, 0.75)] {
+        let (r, mut rec) = hit(&mesh, x, y);
+        assert!((rec.u - x).abs() < 1e-9 && (rec.v - y).abs() < 1e-9, "uv ({}, {}) at ({}, {})", rec.u, rec.v, x, y);
+
+        let emitted = rec.mat.emitted(&r, &rec, rec.u, rec.v, &rec.p);
+        assert_eq!((emitted.x(), emitted.y(), emitted.z()), (2.0, 3.0, 4.0));
+        let material = rec.mat.clone();
+        assert!(!material.scatter(&r, &mut rec, &mut ScatterRecord::new()));
+    }
+
+    // The second quad reflects like a polished metal tinted by its specular color
+    let (r, mut rec) = hit(&mesh, 2.5, 0.5);
+    let mut srec = ScatterRecord::new();
+    let material = rec.mat.clone();
+    assert!(material.scatter(&r, &mut rec, &mut srec));
+    assert!(srec.is_specular);
+    assert_eq!((srec.attenuation.x(), srec.attenuation.y()), (0.9f32 as f64, 0.8f32 as f64));
+    assert!(srec.specular_ray.direction().unit_vector().z() > 0.99);
+
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+/// A unit quad facing +z, with a different color at each vertex
+const PLY_HEADER: &str = "\
+ply
+format {} 1.0
+comment a quad
+element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+const PLY_VERTICES: [([f32; 6], [u8; 3]); 4] = [
+    ([0.0, 0.0, 0.0, 0.0, 0.0, 1.0], [255, 0, 0]),
+    ([1.0, 0.0, 0.0, 0.0, 0.0, 1.0], [255, 0, 0]),
+    ([1.0, 1.0, 0.0, 0.0, 0.0, 1.0], [255, 0, 0]),
+    ([0.0, 1.0, 0.0, 0.0, 0.0, 1.0], [0, 0, 255]),
+];
+
+fn write_ply(dir: &std::path::Path, format: &str) -> PathBuf {
+    let mut bytes = PLY_HEADER.replace("{}", format).into_bytes();
+    let big_endian = format == "binary_big_endian";
+    let int = |i: i32| if big_endian { i.to_be_bytes() } else { i.to_le_bytes() };
+
+    if format == "ascii" {
+        for (v, c) in PLY_VERTICES.iter() {
+            bytes.extend(format!("{} {} {} {} {} {} {} {} {}\n", v[0], v[1], v[2], v[3], v[4], v[5], c[0], c[1], c[2]).bytes());
+        }
+        bytes.extend(b"4 0 1 2 3\n");
+    } else {
+        for (v, c) in PLY_VERTICES.iter() {
+            for f in v {
+                bytes.extend(if big_endian { f.to_be_bytes() } else { f.to_le_bytes() });
+            }
+            bytes.extend(c);
+        }
+        bytes.push(4);
+        for i in 0..4 {
+            bytes.extend(int(i));
+        }
+    }
+
+    let path = dir.join(format!("quad_{}.ply", format));
+    fs::write(&path, bytes).unwrap();
+    path
+}
+
+#[test]
+fn ply_formats_load_normals_and_vertex_colors() {
+    let dir = std::env::temp_dir().join(format!("ray_tracer_ply_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    for format in &["ascii", "binary_little_endian", "binary_big_endian"] {
+        let path = write_ply(&dir, format);
+        let mesh = Mesh::new_from_ply(path.to_str().unwrap(), &Vec3::zero(), 1.0, false, None, Default::default()).unwrap();
+
+        // The triangle with only red vertices is red, the other averages in a blue vertex
+        for &(x, y, color) in &[(0.75, 0.25, (1.0, 0.0, 0.0)), (0.25, 0.75, (0.6666666666666666, 0.0, 0.3333333333333333))] {
+            let (r, mut rec) = hit(&mesh, x, y);
+            assert!(rec.front_face);
+            assert!((rec.normal.z() - 1.0).abs() < 1e-9, "{} normal {:?}", format, rec.normal);
+
+            let mut srec = ScatterRecord::new();
+            let material = rec.mat.clone();
+            assert!(material.scatter(&r, &mut rec, &mut srec));
+            let attenuation = (srec.attenuation.x(), srec.attenuation.y(), srec.attenuation.z());
+            assert!((attenuation.0 - color.0).abs() < 0.003 && (attenuation.2 - color.2).abs() < 0.003,
+                    "{} color {:?} at ({}, {})", format, attenuation, x, y);
+        }
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn malformed_ply_files_are_parse_errors() {
+    let dir = std::env::temp_dir().join(format!("ray_tracer_bad_ply_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let header = |vertices: &str, faces: &str| format!(
+        "ply\nformat ascii 1.0\nelement vertex {}\nproperty float x\nproperty float y\nproperty float z\n\
+         element face {}\nproperty list uchar float vertex_indices\nend_header\n", vertices, faces);
+    let vertices = "0 0 0\n1 0 0\n1 1 0\n";
+    let files = [
+        ("negative", header("3", "1") + vertices + "3 0 1 -1\n"),
+        ("fractional", header("3", "1") + vertices + "3 0 1.5 2\n"),
+        // Counts far beyond what the body holds end in an error rather than a huge allocation
+        ("huge_vertex_count", header("4000000000", "1") + vertices),
+        ("huge_face_count", header("3", "4000000000") + vertices + "3 0 1 2\n"),
+    ];
+
+    for (name, contents) in files.iter() {
+        let path = dir.join(format!("{}.ply", name));
+        fs::write(&path, contents).unwrap();
+        let result = Mesh::new_from_ply(path.to_str().unwrap(), &Vec3::zero(), 1.0, false, None, Default::default());
+        assert!(matches!(result, Err(MeshError::Parse(_))), "{} loaded", name);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn obj_files_without_faces_are_parse_errors() {
+    let dir = std::env::temp_dir().join(format!("ray_tracer_empty_obj_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    for (name, contents) in [("empty", ""), ("vertices_only", "v 0 0 0\nv 1 0 0\nv 1 1 0\n")].iter() {
+        let path = dir.join(format!("{}.obj", name));
+        fs::write(&path, contents).unwrap();
+        let result = Mesh::open(path.to_str().unwrap(), &Vec3::zero(), 1.0, false, None, Default::default());
+        assert!(matches!(result, Err(MeshError::Parse(_))), "{} loaded", name);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Two triangles of a unit quad, the second wound backwards against its normal
+fn stl_triangles() -> [[[f32; 3]; 4]; 2] {
+    [
+        [[0.0, 0.0, 1.0], [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+        [[0.0, 0.0, 1.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]],
+    ]
+}
+
+#[test]
+fn stl_formats_load_consistently_wound_triangles() {
+    let dir = std::env::temp_dir().join(format!("ray_tracer_stl_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut ascii = String::from("solid quad\n");
+    let mut binary = vec![0; 80];
+    binary.extend(2u32.to_le_bytes());
+    for triangle in stl_triangles().iter() {
+        let [n, a, b, c] = triangle;
+        ascii += &format!("facet normal {} {} {}\nouter loop\n", n[0], n[1], n[2]);
+        for v in &[a, b, c] {
+            ascii += &format!("vertex {} {} {}\n", v[0], v[1], v[2]);
+        }
+        ascii += "endloop\nendfacet\n";
+        for f in triangle.iter().flatten() {
+            binary.extend(f.to_le_bytes());
+        }
+        binary.extend([0, 0]);
+    }
+    ascii += "endsolid quad\n";
+    fs::write(dir.join("ascii.stl"), ascii).unwrap();
+    fs::write(dir.join("binary.stl"), binary).unwrap();
+
+    for name in &["ascii.stl", "binary.stl"] {
+        let path = dir.join(name);
+        let mesh = Mesh::open(path.to_str().unwrap(), &Vec3::zero(), 2.0, false, None, Default::default()).unwrap();
+
+        // Both triangles face +z after being scaled up, and their shared vertices have one smooth normal
+        for &(x, y) in &[(1.5, 0.5), (0.5, 1.5)] {
+            let (_, rec) = hit(&mesh, x, y);
+            assert!(rec.front_face, "{} back face at ({}, {})", name, x, y);
+            assert!((rec.normal.z() - 1.0).abs() < 1e-9);
+        }
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,49 @@
+use ray_tracer::onb::Onb;
+use ray_tracer::pdf::{CosinePdf, Pdf};
+use ray_tracer::util;
+use ray_tracer::vec3::Vec3;
+
+/// Normals along, near and away from the axes `build_from_w` picks its helper vector by
+fn normals() -> Vec<Vec3> {
+    vec![
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(-1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 0.0, -3.0),
+        Vec3::new(0.95, 0.1, -0.2),
+        Vec3::new(0.3, -0.5, 0.8),
+    ]
+}
+
+#[test]
+fn basis_is_orthonormal_around_w() {
+    for n in normals() {
+        let uvw = Onb::build_from_w(&n);
+        assert!((uvw.w() - n.unit_vector()).length() < 1e-12, "w {:?} for {:?}", uvw.w(), n);
+        for axis in &[uvw.u(), uvw.v(), uvw.w()] {
+            assert!((axis.length() - 1.0).abs() < 1e-12);
+        }
+        assert!(uvw.u().dot(&uvw.v()).abs() < 1e-12 && uvw.v().dot(&uvw.w()).abs() < 1e-12 && uvw.w().dot(&uvw.u()).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn cosine_samples_lie_in_the_hemisphere_around_w() {
+    util::seed_random(3);
+    let n = 20_000;
+
+    for normal in normals() {
+        let w = normal.unit_vector();
+        let pdf = CosinePdf::new(&normal);
+        let mut cosine_sum = 0.0;
+        for _ in 0..n {
+            let cosine = pdf.generate().unit_vector().dot(&w);
+            assert!(cosine >= 0.0, "sample {} below the surface of {:?}", cosine, normal);
+            cosine_sum += cosine;
+        }
+
+        // Cosine weighted directions average a cosine of 2/3 with their axis
+        let mean = cosine_sum / n as f64;
+        assert!((mean - 2.0 / 3.0).abs() < 0.01, "mean cosine {} around {:?}", mean, normal);
+    }
+}
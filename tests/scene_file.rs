@@ -0,0 +1,47 @@
+use ray_tracer::hittable::{HitRecord, Hittable};
+use ray_tracer::ray::Ray;
+use ray_tracer::scene_file::{parse_scene, SceneFileError};
+use ray_tracer::vec3::Vec3;
+
+#[test]
+fn parse_errors_report_their_line_and_column() {
+    let source = "[camera]\nlookfrom = [0.0, 0.0, 10.0]\nlookat = [0.0, 0.0 0.0]\nvfov = 40.0\n";
+    let error = parse_scene(source, 10, 10).err().unwrap();
+    assert!(matches!(error, SceneFileError::Parse(_)), "{}", error);
+    // Zero based, pointing at the missing comma on the third line
+    assert_eq!(error.line_col(), Some((2, 19)));
+
+    let error = parse_scene("[camera\n", 10, 10).err().unwrap();
+    assert_eq!(error.line_col(), Some((0, 7)));
+
+    // Errors that are not from parsing have no position
+    let unknown = format!("{}\n[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"none\"\n",
+                          source.replace("0.0 0.0]", "0.0, 0.0]"));
+    let error = parse_scene(&unknown, 10, 10).err().unwrap();
+    assert!(matches!(error, SceneFileError::UnknownMaterial(_)), "{}", error);
+    assert_eq!(error.line_col(), None);
+}
+
+#[test]
+fn medium_boundaries_may_leave_out_their_material() {
+    let source = |boundary: &str| format!(r#"
+        [camera]
+        lookfrom = [0.0, 0.0, 10.0]
+        lookat = [0.0, 0.0, 0.0]
+        vfov = 40.0
+
+        [[objects]]
+        type = "constant_medium"
+        boundary = {{ type = "sphere", center = [0.0, 0.0, 0.0], radius = 1.0 {} }}
+        albedo = [0.5, 0.5, 0.5]
+        density = 1000000.0
+    "#, boundary);
+
+    let scene = parse_scene(&source(""), 10, 10).unwrap();
+    let r = Ray::new(Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    let mut rec = HitRecord::new();
+    assert!(scene.objects.hit(&r, 0.001, f64::INFINITY, &mut rec));
+    assert!((rec.t - 9.0).abs() < 1e-3, "hit the medium at {}", rec.t);
+
+    assert!(parse_scene(&source(r#", material = { type = "lambertian", albedo = [1.0, 0.0, 0.0] }"#), 10, 10).is_ok());
+}
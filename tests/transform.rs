@@ -0,0 +1,160 @@
+mod common;
+
+use common::{gray, hit};
+use ray_tracer::aabb::AABB;
+use ray_tracer::aarect::XZRect;
+use ray_tracer::cube::Cube;
+use ray_tracer::hittable::Hittable;
+use ray_tracer::scene_file::parse_scene;
+use ray_tracer::sphere::Sphere;
+use ray_tracer::transform::Transform;
+use ray_tracer::util;
+use ray_tracer::vec3::Vec3;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+#[test]
+fn scaled_sphere_has_ellipsoid_normals() {
+    let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new(&Vec3::zero(), 1.0, gray()));
+    let ellipsoid = Transform::new(sphere)
+        .scale(Vec3::new(2.0, 1.0, 1.0)).unwrap()
+        .translate(Vec3::new(0.0, 0.0, -5.0)).unwrap();
+
+    let rec = hit(&ellipsoid, Vec3::new(5.0, 0.0, -5.0), Vec3::new(-2.0, 0.0, 0.0)).unwrap();
+    assert!((rec.t - 1.5).abs() < 1e-9, "t {}", rec.t);
+    assert!((rec.p - Vec3::new(2.0, 0.0, -5.0)).length() < 1e-9);
+    assert!(rec.front_face);
+
+    // On x²/4 + y² = 1 the normal is proportional to (x / 4, y)
+    let x = 2f64.sqrt();
+    let rec = hit(&ellipsoid, Vec3::new(x, 5.0, -5.0), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+    let expected = Vec3::new(x / 4.0, 0.5f64.sqrt(), 0.0).unit_vector();
+    assert!((rec.p.y() - 0.5f64.sqrt()).abs() < 1e-9);
+    assert!((rec.normal - expected).length() < 1e-9, "normal {:?}", rec.normal);
+
+    // From inside the normal faces the ray and the hit is a back face
+    let rec = hit(&ellipsoid, Vec3::new(0.0, 0.0, -5.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+    assert!(!rec.front_face);
+    assert!((rec.normal.x() + 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn bounding_box_is_tight_around_rotated_cube() {
+    util::seed_random(1);
+    let cube: Arc<dyn Hittable> = Arc::new(Cube::new(Vec3::zero(), Vec3::new(1.0, 2.0, 3.0), gray()));
+    let rotated = Transform::new(cube)
+        .rotate(Vec3::new(1.0, 1.0, 0.0), 30.0).unwrap()
+        .scale(Vec3::new(1.0, 0.5, 2.0)).unwrap()
+        .translate(Vec3::new(-1.0, 4.0, 2.0)).unwrap();
+
+    let mut aabb_box = AABB::new_max();
+    assert!(rotated.bounding_box(0.0, 1.0, &mut aabb_box));
+
+    // Shoot rays at random points of the box from far away and collect the extent of the hits
+    let center = (aabb_box.min() + aabb_box.max()).scale(0.5);
+    let mut extent = AABB::new_max();
+    for _ in 0..20_000 {
+        let origin = center + Vec3::random_unit_vector().scale(100.0);
+        let target = aabb_box.min() + (aabb_box.max() - aabb_box.min()) * Vec3::random();
+        if let Some(rec) = hit(&rotated, origin, target - origin) {
+            extent.add_point(&rec.p);
+        }
+    }
+
+    for axis in 0..3 {
+        assert!(extent.min()[axis] >= aabb_box.min()[axis] - 1e-9 && extent.max()[axis] <= aabb_box.max()[axis] + 1e-9,
+                "hit outside the box on axis {}", axis);
+        assert!(extent.min()[axis] - aabb_box.min()[axis] < 0.1 && aabb_box.max()[axis] - extent.max()[axis] < 0.1,
+                "box is loose on axis {}: {:?} {:?} around {:?} {:?}",
+                axis, aabb_box.min(), aabb_box.max(), extent.min(), extent.max());
+    }
+}
+
+#[test]
+fn instances_share_the_object() {
+    let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new(&Vec3::zero(), 1.0, gray()));
+    let left = Transform::new(sphere.clone()).translate(Vec3::new(-3.0, 0.0, 0.0)).unwrap();
+    let right = Transform::new(sphere.clone())
+        .rotate(Vec3::new(0.0, 0.0, 1.0), 90.0).unwrap()
+        .translate(Vec3::new(3.0, 0.0, 0.0)).unwrap();
+    assert_eq!(Arc::strong_count(&sphere), 3);
+
+    let down = Vec3::new(0.0, -1.0, 0.0);
+    assert!((hit(&left, Vec3::new(-3.0, 5.0, 0.0), down).unwrap().t - 4.0).abs() < 1e-9);
+    assert!((hit(&right, Vec3::new(3.0, 5.0, 0.0), down).unwrap().t - 4.0).abs() < 1e-9);
+    assert!(hit(&left, Vec3::new(3.0, 5.0, 0.0), down).is_none());
+}
+
+#[test]
+fn pdf_of_stretched_light_integrates_to_one() {
+    util::seed_random(2);
+    let rect: Arc<dyn Hittable> = Arc::new(XZRect::new(gray(), -1.0, 1.0, -1.0, 1.0, 0.0));
+    let light = Transform::new(rect)
+        .scale(Vec3::new(2.0, 1.0, 0.5)).unwrap()
+        .rotate(Vec3::new(1.0, 2.0, 3.0), 40.0).unwrap()
+        .translate(Vec3::new(0.5, 0.0, -4.0)).unwrap();
+    let o = Vec3::zero();
+
+    // A uniform estimate over all directions of the integral of the density
+    let n = 200_000;
+    let integral = (0..n).map(|_| light.pdf_value(&o, &Vec3::random_unit_vector())).sum::<f64>() * 4.0 * PI / n as f64;
+    assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+
+    // Sampled directions hit the light with a positive density
+    let misses = (0..1000).filter(|_| {
+        let v = light.random(&o);
+        hit(&light, o, v).is_none() || light.pdf_value(&o, &v) <= 0.0
+    }).count();
+    assert_eq!(misses, 0);
+}
+
+#[test]
+fn scene_files_place_instances() {
+    let source = r#"
+        [camera]
+        lookfrom = [0.0, 0.0, 10.0]
+        lookat = [0.0, 0.0, 0.0]
+        vfov = 40.0
+
+        [[objects]]
+        type = "instances"
+        object = { type = "cube", min = [-1.0, -1.0, -1.0], max = [1.0, 1.0, 1.0], material = { type = "lambertian", albedo = [0.5, 0.5, 0.5] } }
+        instances = [
+            [{ translate = [-3.0, 0.0, 0.0] }],
+            [{ scale = [1.0, 2.0, 1.0] }, { rotate = { axis = [0.0, 0.0, 1.0], angle = 90.0 } }, { translate = [3.0, 0.0, 0.0] }],
+        ]
+    "#;
+    let scene = parse_scene(source, 100, 100).unwrap();
+
+    let down = Vec3::new(0.0, -1.0, 0.0);
+    assert!((hit(&scene.objects, Vec3::new(-3.0, 5.0, 0.0), down).unwrap().t - 4.0).abs() < 1e-9);
+    // The second instance is stretched along y then turned to lie along x
+    assert!((hit(&scene.objects, Vec3::new(4.5, 5.0, 0.0), down).unwrap().t - 4.0).abs() < 1e-9);
+    assert!(hit(&scene.objects, Vec3::new(0.0, 5.0, 0.0), down).is_none());
+}
+
+#[test]
+fn singular_transforms_are_errors() {
+    let cube = || Arc::new(Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0), gray()));
+    assert!(Transform::new(cube()).scale(Vec3::new(0.0, 1.0, 1.0)).is_err());
+    // Looking straight along the up direction leaves the sideways axis undefined
+    assert!(Transform::new(cube()).look_at(Vec3::zero(), Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 1.0, 0.0)).is_err());
+    assert!(Transform::new(cube()).look_at(Vec3::zero(), Vec3::zero(), Vec3::new(0.0, 1.0, 0.0)).is_err());
+
+    let source = |step: &str| format!(r#"
+        [camera]
+        lookfrom = [0.0, 0.0, 10.0]
+        lookat = [0.0, 0.0, 0.0]
+        vfov = 40.0
+
+        [[objects]]
+        type = "transform"
+        steps = [{}]
+        object = {{ type = "sphere", center = [0.0, 0.0, 0.0], radius = 1.0, material = {{ type = "lambertian", albedo = [0.5, 0.5, 0.5] }} }}
+    "#, step);
+    assert!(parse_scene(&source("{ scale = [1.0, 2.0, 1.0] }"), 100, 100).is_ok());
+    let error = parse_scene(&source("{ scale = [0.0, 1.0, 1.0] }"), 100, 100).err().unwrap();
+    assert!(error.to_string().contains("not invertible"), "{}", error);
+    assert!(parse_scene(&source("{ look_at = { from = [0.0, 0.0, 0.0], to = [0.0, 0.0, 0.0] } }"), 100, 100).is_err());
+}
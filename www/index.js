@@ -6,8 +6,11 @@ wasm.init_panic_hook();
 const width_input = document.getElementById('width');
 const height_input = document.getElementById('height');
 const samples_input = document.getElementById('samples');
+const max_depth_input = document.getElementById('max-depth');
 const render_button = document.getElementById('render-button');
 const scene_select = document.getElementById('scene');
+const tonemap_select = document.getElementById('tonemap');
+const exposure_input = document.getElementById('exposure');
 const progress_bar = document.getElementById('progress');
 const loader = document.getElementById('loader');
 
@@ -46,11 +49,15 @@ function render_image(scene, width, height, samples) {
 }
 
 function rendering_mode() {
+    draw_progress(0);
     loader.classList.add("loader");
     width_input.disabled = true;
     height_input.disabled = true;
     samples_input.disabled = true;
+    max_depth_input.disabled = true;
     scene_select.disabled = true;
+    tonemap_select.disabled = true;
+    exposure_input.disabled = true;
 }
 
 function normal_mode() {
@@ -58,7 +65,10 @@ function normal_mode() {
     width_input.disabled = false;
     height_input.disabled = false;
     samples_input.disabled = false;
+    max_depth_input.disabled = false;
     scene_select.disabled = false;
+    tonemap_select.disabled = false;
+    exposure_input.disabled = false;
 }
 
 let worker = new Worker("render.worker.js");
@@ -70,7 +80,8 @@ render_button.addEventListener("click", () => {
             //render_button.value = "Stop";
             //render_button.classList.add("bg-danger")
             render_button.disabled = true;
-            worker.postMessage([scene_select.value, canvas.width, canvas.height, samples_input.value]);
+            worker.postMessage([scene_select.value, canvas.width, canvas.height, parseInt(samples_input.value),
+                tonemap_select.value, parseFloat(exposure_input.value), parseInt(max_depth_input.value)]);
         } else {
             render_button.disabled = true;
             ctx.putImageData(render_image(scene_select.value, canvas.width, canvas.height, samples_input.value), 0, 0);
@@ -88,7 +99,14 @@ render_button.addEventListener("click", () => {
 });
 
 worker.onmessage = (e) => {
-    ctx.putImageData(e.data, 0, 0)
+    if (e.data.progress !== undefined) {
+        draw_progress(e.data.progress);
+        return;
+    }
+    ctx.putImageData(e.data.image, 0, 0)
+    if (!e.data.done) {
+        return;
+    }
     render_button.value = "Render";
     render_button.classList.remove("bg-danger")
     render_button.disabled = false;
@@ -96,5 +114,12 @@ worker.onmessage = (e) => {
 }
 
 const progress_ctx = progress_bar.getContext('2d');
-progress_ctx.fillStyle = '#007bff';
-progress_ctx.fillRect(0, 0, 1000, 1)
\ No newline at end of file
+
+function draw_progress(fraction) {
+    progress_ctx.fillStyle = '#343a40';
+    progress_ctx.fillRect(0, 0, progress_bar.width, progress_bar.height);
+    progress_ctx.fillStyle = '#007bff';
+    progress_ctx.fillRect(0, 0, progress_bar.width * fraction, progress_bar.height);
+}
+
+draw_progress(0)
\ No newline at end of file
@@ -1,6 +1,25 @@
 onmessage = async function(e) {
     const wasm = await import('ray_tracer/ray_tracer')
-    const array = new Uint8ClampedArray(wasm.render_image_array(e.data[0], e.data[1], e.data[2], e.data[3]));
-    const image = new ImageData(array, e.data[1]);
-    postMessage(image);
+    const [scene, width, height, , tonemap, exposure, max_depth] = e.data;
+    const samples = Math.max(e.data[3], 1);
+    const renderer = new wasm.Renderer(scene, width, height);
+    renderer.set_tone_mapping(tonemap, exposure || 0);
+    if (max_depth > 0) {
+        renderer.set_max_depth(max_depth);
+    }
+
+    // Each pass doubles the samples so far, so early previews arrive quickly
+    while (renderer.samples() < samples) {
+        const finished = renderer.samples();
+        const pass = Math.min(Math.max(finished, 1), samples - finished);
+        renderer.render_pass(pass, (pixels, total) => {
+            postMessage({progress: (finished + pass * pixels / total) / samples});
+        });
+
+        const array = new Uint8ClampedArray(renderer.snapshot());
+        const image = new ImageData(array, width);
+        postMessage({image: image, done: renderer.samples() >= samples});
+    }
+
+    renderer.free();
 }